@@ -0,0 +1,159 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{
+  parse_macro_input, punctuated::Punctuated, Data, DeriveInput, Fields, Token,
+};
+
+/// Derives `DiffableModel` for a struct with named fields.
+///
+/// This generates a `{Name}ViewModel` struct with the same fields,
+/// each wrapped in an `Observable`. `diff` only sets the observables
+/// of the fields which changed, compared using [`PartialEq`], so
+/// fields must be `Clone + PartialEq + 'static`.
+///
+/// Fields can be annotated with:
+/// - `#[diffable(skip)]`: the field is left out of the view model.
+/// - `#[diffable(nested)]`: the field is itself a `DiffableModel`,
+///   such as another derived struct or a `Vec` of them, and its own
+///   view model is used instead of an `Observable`.
+#[proc_macro_derive(DiffableModel, attributes(diffable))]
+pub fn derive_diffable_model(
+  input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+
+  derive(input)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+enum FieldKind {
+  Observable,
+  Nested,
+  Skip,
+}
+
+fn derive(input: DeriveInput) -> syn::Result<TokenStream> {
+  let DeriveInput {
+    vis,
+    ident,
+    generics,
+    data,
+    ..
+  } = input;
+
+  let fields = match data {
+    Data::Struct(data) => match data.fields {
+      Fields::Named(fields) => fields.named,
+      _ => {
+        return Err(syn::Error::new(
+          Span::call_site(),
+          "`DiffableModel` can only be derived for structs with named fields",
+        ))
+      }
+    },
+    _ => {
+      return Err(syn::Error::new(
+        Span::call_site(),
+        "`DiffableModel` can only be derived for structs",
+      ))
+    }
+  };
+
+  let view_model_ident = format_ident!("{ident}ViewModel");
+
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  let mut view_model_fields = vec![];
+  let mut view_model_inits = vec![];
+  let mut diffs = vec![];
+
+  for field in fields {
+    let kind = field_kind(&field.attrs)?;
+
+    let field_vis = &field.vis;
+    let field_ident = field.ident.as_ref().unwrap();
+    let ty = &field.ty;
+
+    match kind {
+      FieldKind::Skip => continue,
+      FieldKind::Observable => {
+        view_model_fields.push(quote! {
+          #field_vis #field_ident: ::frappe_tea::prelude::Observable<#ty>
+        });
+        view_model_inits.push(quote! {
+          #field_ident: ::frappe_tea::prelude::Observable::new(
+            ::core::clone::Clone::clone(&self.#field_ident),
+          )
+        });
+        diffs.push(quote! {
+          view_model.#field_ident.set_if_changed(&self.#field_ident);
+        });
+      }
+      FieldKind::Nested => {
+        view_model_fields.push(quote! {
+          #field_vis #field_ident:
+            <#ty as ::frappe_tea::prelude::DiffableModel>::ViewModel
+        });
+        view_model_inits.push(quote! {
+          #field_ident: ::frappe_tea::prelude::DiffableModel::to_view_model(
+            &self.#field_ident,
+          )
+        });
+        diffs.push(quote! {
+          ::frappe_tea::prelude::DiffableModel::diff(
+            &self.#field_ident,
+            &mut view_model.#field_ident,
+          );
+        });
+      }
+    }
+  }
+
+  Ok(quote! {
+    #vis struct #view_model_ident #generics #where_clause {
+      #(#view_model_fields,)*
+    }
+
+    impl #impl_generics ::frappe_tea::prelude::DiffableModel
+      for #ident #ty_generics #where_clause
+    {
+      type ViewModel = #view_model_ident #ty_generics;
+
+      fn to_view_model(&self) -> Self::ViewModel {
+        #view_model_ident {
+          #(#view_model_inits,)*
+        }
+      }
+
+      #[allow(unused_variables)]
+      fn diff(&self, view_model: &mut Self::ViewModel) {
+        #(#diffs)*
+      }
+    }
+  })
+}
+
+fn field_kind(attrs: &[syn::Attribute]) -> syn::Result<FieldKind> {
+  let mut kind = FieldKind::Observable;
+
+  for attr in attrs.iter().filter(|attr| attr.path.is_ident("diffable")) {
+    let idents = attr
+      .parse_args_with(Punctuated::<syn::Ident, Token![,]>::parse_terminated)?;
+
+    for ident in idents {
+      kind = match ident.to_string().as_str() {
+        "skip" => FieldKind::Skip,
+        "nested" => FieldKind::Nested,
+        _ => {
+          return Err(syn::Error::new(
+            ident.span(),
+            "expected `skip` or `nested`",
+          ))
+        }
+      };
+    }
+  }
+
+  Ok(kind)
+}
@@ -0,0 +1,100 @@
+use crate::runtime::IntoMsg;
+use futures::{future::LocalBoxFuture, Future, FutureExt};
+use std::rc::Rc;
+
+/// Side-effects which the runtime should perform on behalf of an
+/// update function, such as HTTP requests or timers.
+///
+/// Each effect is a [`Future`] which may eventually resolve to a
+/// message, which is then fed back into the runtime.
+pub struct Cmd<Msg>(pub(crate) Vec<LocalBoxFuture<'static, Option<Msg>>>);
+
+impl<Msg> Default for Cmd<Msg> {
+  fn default() -> Self {
+    Self::none()
+  }
+}
+
+impl<Msg> Cmd<Msg> {
+  /// A command which does nothing.
+  pub fn none() -> Self {
+    Self(vec![])
+  }
+
+  /// Returns `true` if this command has no effects to run.
+  pub fn is_none(&self) -> bool {
+    self.0.is_empty()
+  }
+}
+
+impl<Msg: 'static> Cmd<Msg> {
+  /// Immediately sends `msg` back to the runtime.
+  pub fn msg(msg: Msg) -> Self {
+    Self(vec![futures::future::ready(Some(msg)).boxed_local()])
+  }
+
+  /// Runs `fut`, sending its output back to the runtime.
+  pub fn perform(fut: impl Future<Output = Msg> + 'static) -> Self {
+    Self(vec![fut.map(Some).boxed_local()])
+  }
+
+  /// Runs `fut`, sending its output, if any, back to the runtime.
+  pub fn perform_maybe<F, IMsg>(fut: F) -> Self
+  where
+    F: Future<Output = IMsg> + 'static,
+    IMsg: IntoMsg<Msg> + 'static,
+  {
+    Self(vec![fut.map(IntoMsg::into_msg).boxed_local()])
+  }
+
+  /// Combines many commands into one, running all of them concurrently.
+  pub fn batch(cmds: impl IntoIterator<Item = Cmd<Msg>>) -> Self {
+    Self(cmds.into_iter().flat_map(|cmd| cmd.0).collect())
+  }
+
+  /// Transforms the messages produced by this command, useful for
+  /// embedding the commands of a nested module.
+  pub fn map<NewMsg: 'static>(
+    self,
+    f: impl Fn(Msg) -> NewMsg + 'static,
+  ) -> Cmd<NewMsg> {
+    let f = Rc::new(f);
+
+    Cmd(
+      self
+        .0
+        .into_iter()
+        .map(|fut| {
+          fut
+            .map(clone!([f], move |msg| msg.map(|msg| f(msg))))
+            .boxed_local()
+        })
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[derive(Debug, PartialEq)]
+  enum Msg {
+    Fetched(u8),
+    Child(u8),
+  }
+
+  #[test]
+  fn effects_send_their_messages_back() {
+    let cmd = Cmd::batch([
+      Cmd::perform(async { Msg::Fetched(1) }),
+      // Effects without an outcome send nothing
+      Cmd::perform_maybe(async {}),
+      Cmd::msg(2).map(Msg::Child),
+    ]);
+
+    let msgs = futures::executor::block_on(futures::future::join_all(cmd.0));
+
+    assert_eq!(msgs, [Some(Msg::Fetched(1)), None, Some(Msg::Child(2))]);
+  }
+}
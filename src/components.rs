@@ -1,3 +1,7 @@
 mod dyn_child;
+mod error_boundary;
+mod stateful;
 
 pub use dyn_child::*;
+pub use error_boundary::{ErrorBoundary, ErrorBoundaryMsg, ViewError};
+pub use stateful::*;
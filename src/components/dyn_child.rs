@@ -1,9 +1,13 @@
+use super::error_boundary::{BoundaryHandle, ViewError};
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+use crate::view::Comment;
 use crate::{
   prelude::Ctx,
-  utils,
-  view::{Comment, Component, IntoView, View, ViewInner, ViewKind},
+  view::{Component, IntoView, View, ViewInner, ViewKind},
 };
-use futures::{Stream, StreamExt};
+use futures::{future, Stream, StreamExt};
+use std::fmt;
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
 use wasm_bindgen::JsCast;
 
 pub struct DynChild<Msg, S, F> {
@@ -26,12 +30,75 @@ where
   }
 }
 
-impl<Msg, S, F, V> IntoView<Msg> for DynChild<Msg, S, F>
+impl<Msg, S: Stream, F> DynChild<Msg, S, Fallible<F>> {
+  /// Like [`DynChild::new`], but building the view can also return an
+  /// error, which is shown by the enclosing
+  /// [`ErrorBoundary`](super::ErrorBoundary).
+  ///
+  /// Unlike panics, these are caught on every target.
+  pub fn fallible<V, E>(cx: Ctx<Msg>, stream: S, f: F) -> Self
+  where
+    F: FnMut(Ctx<Msg>, S::Item) -> Result<V, E>,
+    V: IntoView<Msg>,
+    E: fmt::Display,
+  {
+    Self {
+      cx,
+      stream,
+      view_fn: Fallible(f),
+    }
+  }
+}
+
+/// The view function of a [`DynChild`] built with
+/// [`DynChild::fallible`].
+pub struct Fallible<F>(F);
+
+/// Builds the views of a [`DynChild`].
+pub trait RenderFn<Msg, T> {
+  fn render(
+    &mut self,
+    cx: Ctx<Msg>,
+    item: T,
+  ) -> Result<View<Msg>, ViewError>;
+}
+
+impl<Msg, T, F, V> RenderFn<Msg, T> for F
+where
+  F: FnMut(Ctx<Msg>, T) -> V,
+  V: IntoView<Msg>,
+{
+  fn render(
+    &mut self,
+    cx: Ctx<Msg>,
+    item: T,
+  ) -> Result<View<Msg>, ViewError> {
+    Ok(self(cx, item).into_view())
+  }
+}
+
+impl<Msg, T, F, V, E> RenderFn<Msg, T> for Fallible<F>
+where
+  F: FnMut(Ctx<Msg>, T) -> Result<V, E>,
+  V: IntoView<Msg>,
+  E: fmt::Display,
+{
+  fn render(
+    &mut self,
+    cx: Ctx<Msg>,
+    item: T,
+  ) -> Result<View<Msg>, ViewError> {
+    (self.0)(cx, item)
+      .map(IntoView::into_view)
+      .map_err(|err| ViewError::Failed(err.to_string()))
+  }
+}
+
+impl<Msg, S, F> IntoView<Msg> for DynChild<Msg, S, F>
 where
   Msg: 'static,
   S: Stream + 'static,
-  F: FnMut(Ctx<Msg>, S::Item) -> V + 'static,
-  V: IntoView<Msg>,
+  F: RenderFn<Msg, S::Item> + 'static,
 {
   fn into_view(self) -> crate::view::View<Msg> {
     let Self {
@@ -40,22 +107,31 @@ where
       mut view_fn,
     } = self;
 
-    let kind = ViewKind::new_component("DynChild");
+    let _span = tracing::trace_span!("component", name = "DynChild").entered();
 
-    let (opening_node, children) = match &kind {
+    let mut kind = ViewKind::new_component("DynChild");
+
+    let children = match &kind {
+      ViewKind::Component(Component { children, .. }) => children.clone(),
+      _ => unreachable!(),
+    };
+
+    #[cfg(all(target_arch = "wasm32", feature = "web"))]
+    let opening_node = match &kind {
       ViewKind::Component(Component {
         opening: Comment { node, .. },
-        children,
         ..
-      }) => (
-        node.clone().unchecked_into::<web_sys::Element>(),
-        children.clone(),
-      ),
+      }) => node.clone().unchecked_into::<web_sys::Element>(),
       _ => unreachable!(),
     };
 
+    // Re-renders happen after the boundary finished building its
+    // children, so it needs to be remembered
+    let boundary = BoundaryHandle::current();
+
     let placeholder = placeholder(cx.clone());
 
+    #[cfg(all(target_arch = "wasm32", feature = "web"))]
     opening_node
       .after_with_node_1(&placeholder.0.kind.get_node())
       .unwrap();
@@ -63,7 +139,32 @@ where
     children.borrow_mut().push(placeholder);
 
     let fut = stream.for_each(clone!([cx], move |item| {
-      let view = view_fn(cx.clone(), item).into_view();
+      let _span = tracing::trace_span!("dyn_child_render").entered();
+
+      let res = match &boundary {
+        Some(boundary) => boundary
+          .catch(|| view_fn.render(cx.clone(), item))
+          .and_then(|res| res),
+        None => view_fn.render(cx.clone(), item),
+      };
+
+      let view = match res {
+        Ok(view) => view,
+        Err(err) => {
+          match &boundary {
+            Some(boundary) => boundary.report(err),
+            // The previous view is kept, as there is nothing to show
+            // the error with
+            None => {
+              tracing::error!(%err, "view failed outside of an error boundary")
+            }
+          }
+
+          return future::ready(());
+        }
+      };
+
+      view.attach_to(&cx);
 
       let mut children_borrow = children.borrow_mut();
 
@@ -73,16 +174,25 @@ where
         "DynChild invarient broken, please file a bug report"
       );
 
-      let child_node = view.0.kind.get_node();
+      #[cfg(all(target_arch = "wasm32", feature = "web"))]
+      {
+        let child_node = view.0.kind.get_node();
 
-      opening_node.after_with_node_1(&child_node).unwrap();
+        opening_node.after_with_node_1(&child_node).unwrap();
+      }
 
       *children_borrow = vec![view];
 
-      async {}
+      future::ready(())
     }));
 
-    utils::spawn_local(fut);
+    // Re-rendering stops once the view is dropped, such as when the
+    // app is unmounted
+    let ViewKind::Component(component) = &mut kind else {
+      unreachable!()
+    };
+
+    component.own(cx.spawner.spawn_guarded(fut));
 
     View(ViewInner { cx, kind })
   }
@@ -0,0 +1,397 @@
+use crate::{
+  prelude::Ctx,
+  runtime::MsgDispatcher,
+  view::{IntoView, View, ViewInner, ViewKind},
+};
+use std::{
+  any::Any,
+  cell::RefCell,
+  fmt,
+  panic::{self, AssertUnwindSafe},
+  rc::{Rc, Weak},
+};
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+use wasm_bindgen::JsCast;
+
+type ChildFn<Msg> = Box<dyn FnMut(Ctx<Msg>) -> Result<View<Msg>, ViewError>>;
+type FallbackFn<Msg> = Box<
+  dyn FnMut(
+    Ctx<ErrorBoundaryMsg<Msg>>,
+    &ViewError,
+  ) -> View<ErrorBoundaryMsg<Msg>>,
+>;
+
+thread_local! {
+  /// The error boundaries whose children are being built, innermost
+  /// last.
+  static BOUNDARIES: RefCell<Vec<BoundaryHandle>> = Default::default();
+}
+
+/// An error caught by an [`ErrorBoundary`].
+#[derive(Clone, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum ViewError {
+  /// Building the view panicked.
+  #[display(fmt = "view panicked: {}", _0)]
+  Panicked(String),
+  /// Building the view returned an error.
+  #[display(fmt = "{}", _0)]
+  Failed(String),
+}
+
+/// Messages sent from the fallback view of an [`ErrorBoundary`].
+pub enum ErrorBoundaryMsg<Msg> {
+  /// Tries to build the child view again.
+  Reset,
+  /// Sends a message to the parent.
+  Msg(Msg),
+}
+
+/// Renders a fallback view instead of its child, if building the child
+/// panics, or, with [`ErrorBoundary::fallible`], returns an error.
+///
+/// The view functions of [`DynChild`](super::DynChild)ren built within
+/// the boundary are caught as well, whenever they re-render, including
+/// the errors returned from the ones added with
+/// [`HtmlElement::try_dyn_child`].
+///
+/// Panics can only be caught when the app is built with
+/// `panic = "unwind"`, which is not the default on `wasm32`, so views
+/// which can fail in the browser should return errors instead.
+///
+/// [`HtmlElement::try_dyn_child`]: crate::html::HtmlElement::try_dyn_child
+///
+/// ```no_run
+/// # use frappe_tea::prelude::*;
+/// # fn might_panic(cx: Ctx<()>) -> impl IntoView<()> {
+/// #   p(cx)
+/// # }
+/// # fn view(cx: Ctx<()>) -> impl IntoView<()> {
+/// ErrorBoundary::new(
+///   cx,
+///   |cx| div(cx).child(might_panic),
+///   |cx, err| {
+///     let err = err.to_string();
+///
+///     div(cx)
+///       .child(move |cx| p(cx).text(err))
+///       .child(|cx| {
+///         button(cx)
+///           .text("Retry")
+///           .on("click", |_| Some(ErrorBoundaryMsg::Reset))
+///       })
+///   },
+/// )
+/// # }
+/// ```
+pub struct ErrorBoundary<Msg> {
+  cx: Ctx<Msg>,
+  child_fn: ChildFn<Msg>,
+  fallback_fn: FallbackFn<Msg>,
+}
+
+impl<Msg: 'static> ErrorBoundary<Msg> {
+  pub fn new<V, FB>(
+    cx: Ctx<Msg>,
+    mut child_fn: impl FnMut(Ctx<Msg>) -> V + 'static,
+    fallback_fn: impl FnMut(Ctx<ErrorBoundaryMsg<Msg>>, &ViewError) -> FB + 'static,
+  ) -> Self
+  where
+    V: IntoView<Msg>,
+    FB: IntoView<ErrorBoundaryMsg<Msg>>,
+  {
+    Self::fallible(cx, move |cx| Ok::<_, ViewError>(child_fn(cx)), fallback_fn)
+  }
+
+  /// Like [`ErrorBoundary::new`], but building the child can also
+  /// return an error.
+  pub fn fallible<V, E, FB>(
+    cx: Ctx<Msg>,
+    mut child_fn: impl FnMut(Ctx<Msg>) -> Result<V, E> + 'static,
+    mut fallback_fn: impl FnMut(Ctx<ErrorBoundaryMsg<Msg>>, &ViewError) -> FB
+      + 'static,
+  ) -> Self
+  where
+    V: IntoView<Msg>,
+    E: fmt::Display,
+    FB: IntoView<ErrorBoundaryMsg<Msg>>,
+  {
+    Self {
+      cx,
+      child_fn: Box::new(move |cx| {
+        child_fn(cx)
+          .map(IntoView::into_view)
+          .map_err(|err| ViewError::Failed(err.to_string()))
+      }),
+      fallback_fn: Box::new(move |cx, err| fallback_fn(cx, err).into_view()),
+    }
+  }
+}
+
+impl<Msg: 'static> IntoView<Msg> for ErrorBoundary<Msg> {
+  fn into_view(self) -> View<Msg> {
+    let Self {
+      cx,
+      child_fn,
+      fallback_fn,
+    } = self;
+
+    let _span =
+      tracing::trace_span!("component", name = "ErrorBoundary").entered();
+
+    let mut kind = ViewKind::new_component("ErrorBoundary");
+
+    let ViewKind::Component(component) = &mut kind else {
+      unreachable!()
+    };
+
+    #[cfg(all(target_arch = "wasm32", feature = "web"))]
+    let opening_node = component
+      .opening
+      .node
+      .clone()
+      .unchecked_into::<web_sys::Element>();
+
+    let boundary = Rc::new(Boundary {
+      cx: cx.clone(),
+      children: component.children.clone(),
+      #[cfg(all(target_arch = "wasm32", feature = "web"))]
+      opening_node,
+      child_fn: RefCell::new(child_fn),
+      fallback_fn: RefCell::new(fallback_fn),
+    });
+
+    boundary.render();
+
+    component.own(boundary);
+
+    View(ViewInner { cx, kind })
+  }
+}
+
+struct Boundary<Msg> {
+  cx: Ctx<Msg>,
+  children: Rc<RefCell<Vec<View<Msg>>>>,
+  #[cfg(all(target_arch = "wasm32", feature = "web"))]
+  opening_node: web_sys::Element,
+  child_fn: RefCell<ChildFn<Msg>>,
+  fallback_fn: RefCell<FallbackFn<Msg>>,
+}
+
+impl<Msg: 'static> Boundary<Msg> {
+  /// Builds the child view, or the fallback view if that fails.
+  fn render(self: &Rc<Self>) {
+    let res = self
+      .handle()
+      .catch(|| (self.child_fn.borrow_mut())(self.cx.clone()))
+      .and_then(|res| res);
+
+    match res {
+      Ok(view) => {
+        view.attach_to(&self.cx);
+
+        self.replace(view);
+      }
+      Err(err) => self.show_error(&err),
+    }
+  }
+
+  fn show_error(self: &Rc<Self>, err: &ViewError) {
+    debug!(%err, "error boundary caught an error");
+
+    let parent = self.cx.msg_dispatcher.clone();
+    let spawner = self.cx.spawner.clone();
+    let this = Rc::downgrade(self);
+
+    let cx = Ctx {
+      msg_dispatcher: MsgDispatcher::new(move |msg| match msg {
+        // Deferred, as the fallback view which sent the message is
+        // about to be dropped
+        ErrorBoundaryMsg::Reset => {
+          if let Some(this) = this.upgrade() {
+            spawner.spawn(async move { this.render() });
+          }
+        }
+        ErrorBoundaryMsg::Msg(msg) => parent.dispatch(msg),
+      }),
+      data: self.cx.data.clone(),
+      spawner: self.cx.spawner.clone(),
+    };
+
+    let view = (self.fallback_fn.borrow_mut())(cx, err);
+
+    self.replace(View(ViewInner {
+      cx: self.cx.clone(),
+      kind: ViewKind::Mapped(Box::new(view)),
+    }));
+  }
+
+  fn replace(&self, view: View<Msg>) {
+    #[cfg(all(target_arch = "wasm32", feature = "web"))]
+    self
+      .opening_node
+      .after_with_node_1(&view.0.kind.get_node())
+      .unwrap();
+
+    *self.children.borrow_mut() = vec![view];
+  }
+
+  fn handle(self: &Rc<Self>) -> BoundaryHandle {
+    let spawner = self.cx.spawner.clone();
+    let this = Rc::downgrade(self);
+
+    BoundaryHandle(Rc::new(move |err| {
+      if let Some(this) = Weak::upgrade(&this) {
+        spawner.spawn(async move { this.show_error(&err) });
+      }
+    }))
+  }
+}
+
+/// Reports errors to the [`ErrorBoundary`] a view was built in.
+#[derive(Clone)]
+pub(crate) struct BoundaryHandle(Rc<dyn Fn(ViewError)>);
+
+impl BoundaryHandle {
+  /// The innermost boundary whose children are being built, if any.
+  pub fn current() -> Option<Self> {
+    BOUNDARIES.with(|boundaries| boundaries.borrow().last().cloned())
+  }
+
+  /// Runs `f` within this boundary, turning panics into
+  /// [`ViewError`]s.
+  pub fn catch<T>(&self, f: impl FnOnce() -> T) -> Result<T, ViewError> {
+    BOUNDARIES.with(|boundaries| boundaries.borrow_mut().push(self.clone()));
+
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+
+    BOUNDARIES.with(|boundaries| boundaries.borrow_mut().pop());
+
+    res.map_err(|payload| ViewError::Panicked(panic_message(&*payload)))
+  }
+
+  /// Shows the fallback view of the boundary for `err`.
+  pub fn report(&self, err: ViewError) {
+    (self.0)(err);
+  }
+}
+
+fn panic_message(payload: &dyn Any) -> String {
+  if let Some(msg) = payload.downcast_ref::<&str>() {
+    msg.to_string()
+  } else if let Some(msg) = payload.downcast_ref::<String>() {
+    msg.clone()
+  } else {
+    "unknown panic".to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+
+  #[test]
+  fn error_boundaries_render_fallbacks_and_reset() {
+    use std::{cell::RefCell, rc::Rc};
+
+    let fallback_cx = Rc::new(RefCell::new(None));
+    let attempts = Rc::new(RefCell::new(0));
+    let value = Observable::new(1);
+
+    let app = TestApp::<(), (), _>::new(App::new(
+      || (),
+      |_, _| (),
+      clone!([fallback_cx, attempts, value], move |_, cx| {
+        ErrorBoundary::fallible(
+          cx,
+          clone!([attempts, value], move |cx| {
+            *attempts.borrow_mut() += 1;
+
+            if *attempts.borrow() == 1 {
+              return Err("not ready");
+            }
+
+            Ok(p(cx).dyn_child(&value, |cx, value| {
+              assert_ne!(value, 2, "two is not allowed");
+
+              text(cx, value)
+            }))
+          }),
+          clone!([fallback_cx], move |cx, err| {
+            *fallback_cx.borrow_mut() = Some(cx.clone());
+
+            text(cx, err)
+          }),
+        )
+      }),
+    ));
+
+    assert_eq!(
+      app.render_to_string(),
+      "<!-- <ErrorBoundary> -->not ready<!-- </ErrorBoundary> -->"
+    );
+
+    let reset = || {
+      fallback_cx
+        .borrow()
+        .as_ref()
+        .unwrap()
+        .msg_dispatcher
+        .dispatch(ErrorBoundaryMsg::Reset)
+    };
+
+    reset();
+
+    assert_eq!(
+      app.render_to_string(),
+      "<!-- <ErrorBoundary> --><p><!-- <DynChild> -->1<!-- </DynChild> \
+       --></p><!-- </ErrorBoundary> -->"
+    );
+
+    value.set(2);
+
+    assert_eq!(
+      app.render_to_string(),
+      "<!-- <ErrorBoundary> -->view panicked: assertion `left != right` \
+       failed: two is not allowed\n  left: 2\n right: 2<!-- \
+       </ErrorBoundary> -->"
+    );
+  }
+
+  #[test]
+  fn error_boundaries_catch_errors_returned_by_dyn_children() {
+    let value = Observable::new(1);
+
+    let app = TestApp::<(), (), _>::new(App::new(
+      || (),
+      |_, _| (),
+      clone!([value], move |_, cx| {
+        ErrorBoundary::new(
+          cx,
+          clone!([value], move |cx| {
+            p(cx).try_dyn_child(&value, |cx, value| {
+              if value == 2 {
+                return Err("two is not allowed");
+              }
+
+              Ok(text(cx, value))
+            })
+          }),
+          |cx, err| text(cx, err),
+        )
+      }),
+    ));
+
+    assert_eq!(
+      app.render_to_string(),
+      "<!-- <ErrorBoundary> --><p><!-- <DynChild> -->1<!-- </DynChild> \
+       --></p><!-- </ErrorBoundary> -->"
+    );
+
+    value.set(2);
+
+    assert_eq!(
+      app.render_to_string(),
+      "<!-- <ErrorBoundary> -->two is not allowed<!-- </ErrorBoundary> -->"
+    );
+  }
+}
@@ -0,0 +1,238 @@
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+use crate::view::{Comment, Component};
+use crate::{
+  observable::IntoStream,
+  prelude::Ctx,
+  runtime::{DiffableModel, IntoUpdate, MsgDispatcher, Runtime},
+  utils,
+  view::{IntoView, View, ViewInner, ViewKind},
+};
+use futures::{stream::LocalBoxStream, StreamExt};
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+use wasm_bindgen::JsCast;
+
+/// Sends messages from a [`StatefulComponent`] to its parent.
+#[derive(educe::Educe)]
+#[educe(Clone)]
+pub struct Emitter<ParentMsg>(MsgDispatcher<ParentMsg>);
+
+impl<ParentMsg: 'static> Emitter<ParentMsg> {
+  /// Sends `msg` to the parent's update function.
+  pub fn emit(&self, msg: ParentMsg) {
+    self.0.dispatch(msg);
+  }
+}
+
+/// A component with its own model, message type and update loop,
+/// which is mounted inside of a parent view.
+///
+/// The parent can feed values into the component with
+/// [`StatefulComponent::prop`], and the component can notify the
+/// parent through the [`Emitter`] passed to its update function.
+///
+/// The component's update loop is cancelled when its view is dropped.
+pub struct StatefulComponent<ParentMsg, Msg, IF, UF, VF> {
+  cx: Ctx<ParentMsg>,
+  name: String,
+  init_fn: IF,
+  update_fn: UF,
+  view_fn: VF,
+  props: Vec<LocalBoxStream<'static, Msg>>,
+}
+
+impl<ParentMsg, M, Msg, IF, UF, U, VF, V>
+  StatefulComponent<ParentMsg, Msg, IF, UF, VF>
+where
+  ParentMsg: 'static,
+  M: DiffableModel + 'static,
+  Msg: 'static,
+  IF: FnOnce() -> M,
+  UF: FnMut(M, Msg, &Emitter<ParentMsg>) -> U + 'static,
+  U: IntoUpdate<M, Msg>,
+  VF: FnOnce(&M::ViewModel, Ctx<Msg>) -> V,
+  V: IntoView<Msg>,
+{
+  pub fn new(
+    cx: Ctx<ParentMsg>,
+    name: impl ToString,
+    init_fn: IF,
+    update_fn: UF,
+    view_fn: VF,
+  ) -> Self {
+    Self {
+      cx,
+      name: name.to_string(),
+      init_fn,
+      update_fn,
+      view_fn,
+      props: vec![],
+    }
+  }
+
+  /// Sends a message, built with `f`, to the component for each value
+  /// yielded by `values`, such as an
+  /// [`Observable`](crate::prelude::Observable).
+  pub fn prop<S>(
+    mut self,
+    values: S,
+    f: impl FnMut(S::Item) -> Msg + 'static,
+  ) -> Self
+  where
+    S: IntoStream,
+  {
+    self.props.push(values.into_stream().map(f).boxed_local());
+
+    self
+  }
+}
+
+impl<ParentMsg, M, Msg, IF, UF, U, VF, V> IntoView<ParentMsg>
+  for StatefulComponent<ParentMsg, Msg, IF, UF, VF>
+where
+  ParentMsg: 'static,
+  M: DiffableModel + 'static,
+  M::ViewModel: 'static,
+  Msg: 'static,
+  IF: FnOnce() -> M,
+  UF: FnMut(M, Msg, &Emitter<ParentMsg>) -> U + 'static,
+  U: IntoUpdate<M, Msg> + 'static,
+  VF: FnOnce(&M::ViewModel, Ctx<Msg>) -> V,
+  V: IntoView<Msg>,
+{
+  fn into_view(self) -> View<ParentMsg> {
+    let Self {
+      cx,
+      name,
+      init_fn,
+      mut update_fn,
+      view_fn,
+      props,
+    } = self;
+
+    let _span = tracing::trace_span!("component", name).entered();
+
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+
+    let model = init_fn();
+    let view_model = model.to_view_model();
+
+    let child_view =
+      view_fn(&view_model, cx.with_msg_sender(tx.clone())).into_view();
+
+    let emitter = Emitter(cx.msg_dispatcher.clone());
+
+    let mut rt = Runtime::new(
+      Some(model),
+      view_model,
+      move |model, msg| update_fn(model, msg, &emitter),
+      tx,
+      rx,
+      utils::Executor::default(),
+    );
+
+    for prop in props {
+      rt.forward_stream(prop);
+    }
+
+    let mut kind = ViewKind::new_component(&name);
+
+    let ViewKind::Component(component) = &mut kind else {
+      unreachable!()
+    };
+
+    #[cfg(all(target_arch = "wasm32", feature = "web"))]
+    {
+      let Component {
+        opening: Comment { node, .. },
+        ..
+      } = component;
+
+      node
+        .unchecked_ref::<web_sys::Element>()
+        .after_with_node_1(&child_view.0.kind.get_node())
+        .unwrap();
+    }
+
+    component.children.borrow_mut().push(View(ViewInner {
+      cx: cx.clone(),
+      kind: ViewKind::Mapped(Box::new(child_view)),
+    }));
+
+    component.own(cx.spawner.spawn_guarded(async move {
+      rt.run_until_stopped().await
+    }));
+
+    View(ViewInner { cx, kind })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+
+  #[test]
+  fn stateful_components_receive_props_and_emit() {
+    #[derive(DiffableModel)]
+    struct Model {
+      count: usize,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Increment,
+      Echoed(usize),
+    }
+
+    #[derive(DiffableModel)]
+    struct Echo {
+      last: usize,
+    }
+
+    enum EchoMsg {
+      Set(usize),
+    }
+
+    let mut app = TestApp::new(App::new(
+      || Model { count: 0 },
+      |model, msg| match msg {
+        Msg::Increment => Model {
+          count: model.count + 1,
+        },
+        Msg::Echoed(_) => model,
+      },
+      |vm: &ModelViewModel, cx| {
+        let count = vm.count.clone();
+
+        div(cx).child(move |cx| {
+          StatefulComponent::new(
+            cx,
+            "Echo",
+            || Echo { last: 0 },
+            |_, EchoMsg::Set(n), emitter: &Emitter<Msg>| {
+              emitter.emit(Msg::Echoed(n));
+
+              Echo { last: n }
+            },
+            |vm: &EchoViewModel, cx| p(cx).dyn_text(&vm.last),
+          )
+          .prop(count, EchoMsg::Set)
+        })
+      },
+    ));
+
+    app.run_until_idle();
+
+    app.dispatch(Msg::Increment);
+    app.run_until_idle();
+
+    assert_eq!(
+      app.messages(),
+      [Msg::Echoed(0), Msg::Increment, Msg::Echoed(1)]
+    );
+    assert_eq!(
+      app.render_to_string(),
+      "<div><!-- <Echo> --><p><!-- <DynChild> -->1<!-- </DynChild> \
+       --></p><!-- </Echo> --></div>"
+    );
+  }
+}
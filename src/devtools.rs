@@ -0,0 +1,557 @@
+//! Inspecting apps with the Redux DevTools extension.
+//!
+//! Each processed message is reported as an action, along with the
+//! resulting model, and the "jump to state" and "skip action" commands
+//! of the extension are applied through time-travel.
+
+use crate::{middleware::Middleware, time_travel::TimeTravelHandle};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{cell::RefCell, fmt, rc::Rc};
+
+/// The connection to the devtools, exchanging messages of the Redux
+/// DevTools extension protocol.
+///
+/// Messages sent to the devtools are either
+/// `{ "type": "INIT", "state": .. }`,
+/// `{ "type": "ACTION", "action": { "type": .. }, "state": .. }`, or
+/// `{ "type": "STATE", "payload": .. }` with the lifted state of the
+/// extension, once a command of the devtools changed the model. The
+/// ones received are the same as the ones given to listeners of the
+/// extension's `connect().subscribe()`.
+pub trait DevToolsTransport {
+  /// Sends `message` to the devtools.
+  fn send(&self, message: Value);
+
+  /// Calls `listener` with every message received from the devtools.
+  fn subscribe(&self, listener: Box<dyn Fn(Value)>);
+}
+
+/// A transport which stands in for the extension, such as for tests.
+///
+/// Clones share the same messages and listeners.
+#[derive(Clone, Default)]
+pub struct MemoryTransport(Rc<MemoryTransportInner>);
+
+type Listener = Rc<dyn Fn(Value)>;
+
+#[derive(Default)]
+struct MemoryTransportInner {
+  sent: RefCell<Vec<Value>>,
+  listeners: RefCell<Vec<Listener>>,
+}
+
+impl MemoryTransport {
+  /// Every message sent to the devtools so far, oldest first.
+  pub fn sent(&self) -> Vec<Value> {
+    self.0.sent.borrow().clone()
+  }
+
+  /// Delivers `message` to the app, as if it had been sent by the
+  /// devtools.
+  pub fn receive(&self, message: Value) {
+    let listeners = self.0.listeners.borrow().clone();
+
+    for listener in listeners {
+      listener(message.clone());
+    }
+  }
+}
+
+impl DevToolsTransport for MemoryTransport {
+  fn send(&self, message: Value) {
+    self.0.sent.borrow_mut().push(message);
+  }
+
+  fn subscribe(&self, listener: Box<dyn Fn(Value)>) {
+    self.0.listeners.borrow_mut().push(listener.into());
+  }
+}
+
+/// Connects to the extension through
+/// `window.__REDUX_DEVTOOLS_EXTENSION__`.
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+pub struct ExtensionTransport {
+  connection: wasm_bindgen::JsValue,
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+impl ExtensionTransport {
+  /// Connects to the extension, listing the app under `name`.
+  ///
+  /// Returns `None` if the extension is not installed.
+  pub fn connect(name: &str) -> Option<Self> {
+    use js_sys::{Function, Object, Reflect};
+    use wasm_bindgen::JsCast;
+
+    let extension = Reflect::get(
+      &gloo::utils::window(),
+      &"__REDUX_DEVTOOLS_EXTENSION__".into(),
+    )
+    .ok()
+    .filter(|extension| !extension.is_undefined())?;
+
+    let connect = Reflect::get(&extension, &"connect".into())
+      .ok()?
+      .dyn_into::<Function>()
+      .ok()?;
+
+    let options = Object::new();
+
+    Reflect::set(&options, &"name".into(), &name.into()).ok()?;
+
+    let connection = connect.call1(&extension, &options).ok()?;
+
+    Some(Self { connection })
+  }
+
+  fn call(&self, method: &str, args: &js_sys::Array) {
+    use wasm_bindgen::JsCast;
+
+    let res = js_sys::Reflect::get(&self.connection, &method.into())
+      .ok()
+      .and_then(|f| f.dyn_into::<js_sys::Function>().ok())
+      .map(|f| f.apply(&self.connection, args));
+
+    if !matches!(res, Some(Ok(_))) {
+      debug!(method, "failed to call the devtools extension");
+    }
+  }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+impl DevToolsTransport for ExtensionTransport {
+  fn send(&self, message: Value) {
+    let to_js = |value: &Value| {
+      js_sys::JSON::parse(&value.to_string())
+        .unwrap_or(wasm_bindgen::JsValue::UNDEFINED)
+    };
+
+    match message["type"].as_str() {
+      Some("INIT") => {
+        self.call("init", &js_sys::Array::of1(&to_js(&message["state"])))
+      }
+      Some("ACTION") => self.call(
+        "send",
+        &js_sys::Array::of2(
+          &to_js(&message["action"]),
+          &to_js(&message["state"]),
+        ),
+      ),
+      // Sending without an action replaces the lifted state
+      Some("STATE") => self.call(
+        "send",
+        &js_sys::Array::of2(
+          &wasm_bindgen::JsValue::NULL,
+          &to_js(&message["payload"]),
+        ),
+      ),
+      _ => {}
+    }
+  }
+
+  fn subscribe(&self, listener: Box<dyn Fn(Value)>) {
+    use wasm_bindgen::{closure::Closure, JsValue};
+
+    let listener = Closure::<dyn Fn(JsValue)>::new(move |message| {
+      let message = js_sys::JSON::stringify(&message)
+        .ok()
+        .and_then(|message| message.as_string())
+        .and_then(|message| serde_json::from_str(&message).ok());
+
+      if let Some(message) = message {
+        listener(message);
+      }
+    });
+
+    self.call("subscribe", &js_sys::Array::of1(listener.as_ref()));
+
+    // The extension keeps calling the listener for as long as the
+    // page is open
+    listener.forget();
+  }
+}
+
+/// Reports an app to the Redux DevTools extension.
+///
+/// ```no_run
+/// # use frappe_tea::prelude::*;
+/// # #[derive(Clone, Debug, DiffableModel)]
+/// # struct Model {
+/// #   count: i32,
+/// # }
+/// # #[derive(Clone, Debug)]
+/// # enum Msg {
+/// #   Increment,
+/// # }
+/// # fn init() -> Model {
+/// #   Model { count: 0 }
+/// # }
+/// # fn update(model: Model, _: Msg) -> Model {
+/// #   model
+/// # }
+/// # fn view(_: &ModelViewModel, cx: Ctx<Msg>) -> impl IntoView<Msg> {
+/// #   div(cx)
+/// # }
+/// # #[cfg(all(target_arch = "wasm32", feature = "web"))]
+/// App::new(init, update, view).devtools(DevTools::new(
+///   ExtensionTransport::connect("counter").unwrap(),
+/// ))
+/// # ;
+/// ```
+pub struct DevTools<M, Msg> {
+  transport: Rc<dyn DevToolsTransport>,
+  state_fn: fn(&M) -> Value,
+  action_fn: fn(&Msg) -> Value,
+}
+
+impl<M, Msg> DevTools<M, Msg> {
+  /// Reports models and messages with their [`Debug`](fmt::Debug)
+  /// representation.
+  pub fn new(transport: impl DevToolsTransport + 'static) -> Self
+  where
+    M: fmt::Debug,
+    Msg: fmt::Debug,
+  {
+    Self {
+      transport: Rc::new(transport),
+      state_fn: |model| Value::String(format!("{model:#?}")),
+      action_fn: |msg| json!({ "type": format!("{msg:?}") }),
+    }
+  }
+
+  /// Reports models and messages serialized with serde, so the
+  /// devtools can show them as trees.
+  ///
+  /// Enum variants are reported as the action type, with their fields
+  /// as the payload.
+  pub fn serde(transport: impl DevToolsTransport + 'static) -> Self
+  where
+    M: Serialize,
+    Msg: Serialize,
+  {
+    Self {
+      transport: Rc::new(transport),
+      state_fn: |model| to_value(model, "model"),
+      action_fn: |msg| match serde_json::to_value(msg) {
+        Ok(msg) => to_action(msg),
+        Err(_) => json!({ "type": to_value(msg, "message") }),
+      },
+    }
+  }
+
+  /// Sends the initial `model`, and applies the commands of the
+  /// devtools through `history` from now on.
+  pub(crate) fn connect(
+    self,
+    model: &M,
+    history: TimeTravelHandle<Msg>,
+  ) -> Bridge<M, Msg>
+  where
+    Msg: Clone + 'static,
+  {
+    let state = (self.state_fn)(model);
+
+    self.transport.send(json!({
+      "type": "INIT",
+      "state": state,
+    }));
+
+    let steps = Rc::<RefCell<Vec<Option<usize>>>>::default();
+
+    self.transport.subscribe(Box::new(clone!([history, steps], move |msg| {
+      apply_command(&msg, &history, &steps.borrow())
+    })));
+
+    Bridge {
+      devtools: self,
+      history,
+      steps,
+      actions: vec![],
+      states: vec![state],
+    }
+  }
+}
+
+/// Serializes `value`, or, if that fails, logs the error and returns
+/// its message, so the devtools show what went wrong.
+fn to_value(value: &impl Serialize, what: &str) -> Value {
+  serde_json::to_value(value).unwrap_or_else(|err| {
+    tracing::error!("failed to serialize the {what} for the devtools: {err}");
+
+    Value::String(format!("failed to serialize the {what}: {err}"))
+  })
+}
+
+/// Turns a serialized message into an action, which needs a `type`.
+fn to_action(msg: Value) -> Value {
+  match msg {
+    // Unit variants
+    Value::String(variant) => json!({ "type": variant }),
+    // Variants with fields
+    Value::Object(fields) if fields.len() == 1 => {
+      let (variant, payload) = fields.into_iter().next().unwrap();
+
+      json!({ "type": variant, "payload": payload })
+    }
+    payload => json!({ "type": "Msg", "payload": payload }),
+  }
+}
+
+/// Applies a command received from the devtools, where `steps` maps
+/// each action id to the step it was recorded at.
+fn apply_command<Msg: Clone>(
+  msg: &Value,
+  history: &TimeTravelHandle<Msg>,
+  steps: &[Option<usize>],
+) {
+  if msg["type"] != "DISPATCH" {
+    return;
+  }
+
+  let payload = &msg["payload"];
+
+  let Some(action_id) = payload["actionId"]
+    .as_u64()
+    .or_else(|| payload["index"].as_u64())
+  else {
+    return;
+  };
+
+  // Action `0` is the initial model
+  let step = match action_id.checked_sub(1) {
+    Some(index) => steps.get(index as usize).copied().flatten(),
+    None => Some(0),
+  };
+
+  let Some(step) = step else {
+    debug!(action_id, "the devtools action is no longer recorded");
+
+    return;
+  };
+
+  match payload["type"].as_str() {
+    Some("JUMP_TO_STATE" | "JUMP_TO_ACTION") => history.jump_to(step),
+    Some("TOGGLE_ACTION") if step > 0 => history.toggle(step - 1),
+    kind => {
+      debug!(?kind, "unsupported devtools command");
+    }
+  }
+}
+
+/// Reports every update to the devtools.
+pub(crate) struct Bridge<M, Msg> {
+  devtools: DevTools<M, Msg>,
+  history: TimeTravelHandle<Msg>,
+  /// The step each action was recorded at, by action id, or `None` if
+  /// it was since discarded.
+  steps: Rc<RefCell<Vec<Option<usize>>>>,
+  /// Every action sent so far.
+  actions: Vec<Value>,
+  /// The state after each action, starting with the initial one.
+  states: Vec<Value>,
+}
+
+impl<M, Msg: Clone> Bridge<M, Msg> {
+  /// The id of the action the current step was recorded with.
+  fn current_action_id(&self) -> usize {
+    let step = self.history.current_step();
+
+    if step == 0 {
+      return 0;
+    }
+
+    self
+      .steps
+      .borrow()
+      .iter()
+      .rposition(|recorded| *recorded == Some(step))
+      .map_or(0, |index| index + 1)
+  }
+
+  /// The state kept by the extension, which lists every action along
+  /// with the state after it.
+  fn lifted_state(&self) -> Value {
+    let perform = |action: &Value| {
+      json!({ "type": "PERFORM_ACTION", "action": action, "timestamp": 0 })
+    };
+
+    let init = json!({ "type": "@@INIT" });
+
+    let actions_by_id = std::iter::once(&init)
+      .chain(&self.actions)
+      .enumerate()
+      .map(|(id, action)| (id.to_string(), perform(action)))
+      .collect::<serde_json::Map<_, _>>();
+
+    let skipped = self
+      .steps
+      .borrow()
+      .iter()
+      .enumerate()
+      .filter(|(_, step)| {
+        step.is_some_and(|step| step > 0 && self.history.is_skipped(step - 1))
+      })
+      .map(|(index, _)| index + 1)
+      .collect::<Vec<_>>();
+
+    json!({
+      "actionsById": actions_by_id,
+      "computedStates": self
+        .states
+        .iter()
+        .map(|state| json!({ "state": state }))
+        .collect::<Vec<_>>(),
+      "committedState": self.states[0],
+      "currentStateIndex": self.current_action_id(),
+      "nextActionId": self.actions.len() + 1,
+      "skippedActionIds": skipped,
+      "stagedActionIds": (0..=self.actions.len()).collect::<Vec<_>>(),
+      "isLocked": false,
+      "isPaused": self.history.is_paused(),
+      "monitorState": {},
+    })
+  }
+}
+
+impl<M, Msg: Clone> Middleware<M, Msg> for Bridge<M, Msg> {
+  fn after_update(&mut self, msg: &Msg, _: &M, model: &M) {
+    let step = self.history.current_step();
+
+    let mut steps = self.steps.borrow_mut();
+
+    // A message received after jumping back discards the steps which
+    // came after it
+    for recorded in steps.iter_mut() {
+      if recorded.is_some_and(|recorded| recorded >= step) {
+        *recorded = None;
+      }
+    }
+
+    steps.push(Some(step));
+
+    let action = (self.devtools.action_fn)(msg);
+    let state = (self.devtools.state_fn)(model);
+
+    self.actions.push(action.clone());
+    self.states.push(state.clone());
+
+    self.devtools.transport.send(json!({
+      "type": "ACTION",
+      "action": action,
+      "state": state,
+    }));
+  }
+
+  /// Recomputes the state after every action which came after the
+  /// toggled one.
+  fn after_toggle(&mut self, first: usize, models: &[M]) {
+    let steps = self.steps.borrow();
+
+    for (index, step) in steps.iter().enumerate() {
+      let Some(model) = step
+        .and_then(|step| step.checked_sub(first))
+        .and_then(|offset| models.get(offset))
+      else {
+        continue;
+      };
+
+      self.states[index + 1] = (self.devtools.state_fn)(model);
+    }
+  }
+
+  /// Sends the model recomputed for the current step, along with the
+  /// states recomputed by [`Bridge::after_toggle`].
+  fn after_time_travel(&mut self, model: &M) {
+    let id = self.current_action_id();
+
+    self.states[id] = (self.devtools.state_fn)(model);
+
+    self.devtools.transport.send(json!({
+      "type": "STATE",
+      "payload": self.lifted_state(),
+    }));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    prelude::*,
+    testing::{
+      fixtures::{self, CounterMsg::Add},
+      TestApp,
+    },
+  };
+
+  #[test]
+  fn devtools_report_updates_and_apply_commands() {
+    use serde_json::json;
+
+    let transport = MemoryTransport::default();
+
+    let mut app = TestApp::new(
+      fixtures::counter(0).devtools(DevTools::serde(transport.clone())),
+    );
+
+    app.dispatch(Add(1));
+    app.dispatch(Add(10));
+    app.run_until_idle();
+
+    assert_eq!(
+      &transport.sent()[..3],
+      [
+        json!({ "type": "INIT", "state": { "count": 0 } }),
+        json!({
+          "type": "ACTION",
+          "action": { "type": "Add", "payload": 1 },
+          "state": { "count": 1 },
+        }),
+        json!({
+          "type": "ACTION",
+          "action": { "type": "Add", "payload": 10 },
+          "state": { "count": 11 },
+        }),
+      ]
+    );
+
+    app.dispatch(Add(100));
+    app.run_until_idle();
+
+    let command = |kind: &str, action_id: u64| {
+      json!({
+        "type": "DISPATCH",
+        "payload": { "type": kind, "actionId": action_id },
+      })
+    };
+
+    transport.receive(command("JUMP_TO_STATE", 1));
+    app.run_until_idle();
+
+    assert_eq!(app.model().count, 1);
+
+    // Skipping the first action keeps the ones after it
+    transport.receive(command("JUMP_TO_STATE", 2));
+    transport.receive(command("TOGGLE_ACTION", 1));
+    app.run_until_idle();
+
+    assert_eq!(app.model().count, 10);
+    assert_eq!(app.render_to_string(), fixtures::rendered(10));
+
+    // The recomputed states are sent back
+    let lifted = transport.sent().pop().unwrap();
+
+    assert_eq!(lifted["type"], "STATE");
+    assert_eq!(lifted["payload"]["currentStateIndex"], 2);
+    assert_eq!(lifted["payload"]["skippedActionIds"], json!([1]));
+    assert_eq!(
+      lifted["payload"]["computedStates"],
+      json!([
+        { "state": { "count": 0 } },
+        { "state": { "count": 0 } },
+        { "state": { "count": 10 } },
+        { "state": { "count": 110 } },
+      ])
+    );
+  }
+}
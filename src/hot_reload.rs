@@ -0,0 +1,260 @@
+//! Carrying the model of a running app over to a new build.
+
+use crate::observable::IntoStream;
+use futures::{
+  channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+  stream::LocalBoxStream,
+  StreamExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+  cell::RefCell,
+  rc::Rc,
+  task::{self, Poll},
+};
+
+/// Somewhere the serialized model is kept while a new build of the app
+/// is being loaded.
+pub trait StateStore {
+  /// Saves the serialized model, replacing any previous one.
+  fn save(&self, state: String);
+
+  /// Takes the saved model out of the store, if there is one.
+  fn take(&self) -> Option<String>;
+}
+
+/// Keeps the model in memory, for reloading apps within the same
+/// process, such as in tests.
+///
+/// Clones share the same state.
+#[derive(Clone, Default)]
+pub struct MemoryStore(Rc<RefCell<Option<String>>>);
+
+impl StateStore for MemoryStore {
+  fn save(&self, state: String) {
+    *self.0.borrow_mut() = Some(state);
+  }
+
+  fn take(&self) -> Option<String> {
+    self.0.borrow_mut().take()
+  }
+}
+
+/// Keeps the model in `sessionStorage`, which survives page reloads.
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+pub struct SessionStore {
+  key: String,
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+impl SessionStore {
+  pub fn new(key: impl ToString) -> Self {
+    Self {
+      key: key.to_string(),
+    }
+  }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+impl StateStore for SessionStore {
+  fn save(&self, state: String) {
+    use gloo::storage::Storage;
+
+    gloo::storage::SessionStorage::raw()
+      .set_item(&self.key, &state)
+      .expect("model to fit in `sessionStorage`");
+  }
+
+  fn take(&self) -> Option<String> {
+    use gloo::storage::Storage;
+
+    let storage = gloo::storage::SessionStorage::raw();
+
+    let state = storage.get_item(&self.key).ok().flatten();
+
+    let _ = storage.remove_item(&self.key);
+
+    state
+  }
+}
+
+/// Signals new builds by hand, standing in for a file watcher or a
+/// dev server.
+///
+/// ```no_run
+/// # use frappe_tea::prelude::*;
+/// # #[derive(DiffableModel, serde::Serialize, serde::Deserialize)]
+/// # struct Model {
+/// #   count: i32,
+/// # }
+/// # fn init() -> Model {
+/// #   Model { count: 0 }
+/// # }
+/// # fn update(model: Model, _: ()) -> Model {
+/// #   model
+/// # }
+/// # fn view(_: &ModelViewModel, cx: Ctx<()>) -> impl IntoView<()> {
+/// #   div(cx)
+/// # }
+/// let (trigger, reloads) = ReloadTrigger::new();
+///
+/// let app = App::new_hot_reloadable(
+///   HotReload::new(MemoryStore::default(), reloads),
+///   init,
+///   update,
+///   view,
+/// );
+///
+/// trigger.reload();
+/// ```
+#[derive(Clone)]
+pub struct ReloadTrigger(UnboundedSender<()>);
+
+impl ReloadTrigger {
+  /// Creates a trigger, along with the stream of reloads to pass to
+  /// [`HotReload::new`].
+  pub fn new() -> (Self, UnboundedReceiver<()>) {
+    let (tx, rx) = mpsc::unbounded();
+
+    (Self(tx), rx)
+  }
+
+  /// Signals that a new build is available.
+  pub fn reload(&self) {
+    let _ = self.0.unbounded_send(());
+  }
+}
+
+/// Signals a new build every time the dev server at `url` sends a
+/// message over a WebSocket.
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+pub fn websocket_reloads(url: &str) -> LocalBoxStream<'static, ()> {
+  match gloo::net::websocket::futures::WebSocket::open(url) {
+    Ok(ws) => ws
+      .filter_map(|msg| futures::future::ready(msg.ok().map(|_| ())))
+      .boxed_local(),
+    Err(err) => {
+      tracing::warn!(%err, "failed to connect to the hot-reload server");
+
+      futures::stream::pending().boxed_local()
+    }
+  }
+}
+
+/// Configures how an app carries its model over to new builds.
+pub struct HotReload {
+  store: Box<dyn StateStore>,
+  reloads: LocalBoxStream<'static, ()>,
+}
+
+impl HotReload {
+  /// Saves the model to `store` whenever `reloads` yields.
+  pub fn new<S>(store: impl StateStore + 'static, reloads: S) -> Self
+  where
+    S: IntoStream<Item = ()>,
+  {
+    Self {
+      store: Box::new(store),
+      reloads: reloads.into_stream().fuse().boxed_local(),
+    }
+  }
+
+  /// Restores the model saved by a previous build, if any.
+  ///
+  /// Models which no longer deserialize, such as when their fields
+  /// changed between builds, are discarded.
+  pub(crate) fn restore<M: DeserializeOwned>(&self) -> Option<M> {
+    let state = self.store.take()?;
+
+    match serde_json::from_str(&state) {
+      Ok(model) => Some(model),
+      Err(err) => {
+        tracing::warn!(%err, "discarding the model saved by a previous build");
+
+        None
+      }
+    }
+  }
+
+  pub(crate) fn into_reloader<M: Serialize>(self) -> Reloader<M> {
+    Reloader {
+      store: self.store,
+      reloads: self.reloads,
+      serialize: serde_json::to_string::<M>,
+    }
+  }
+}
+
+/// The hot-reload state kept by the runtime.
+pub(crate) struct Reloader<M> {
+  store: Box<dyn StateStore>,
+  reloads: LocalBoxStream<'static, ()>,
+  serialize: fn(&M) -> serde_json::Result<String>,
+}
+
+impl<M> Reloader<M> {
+  pub fn poll_reload(&mut self, cx: &mut task::Context) -> Poll<()> {
+    match self.reloads.poll_next_unpin(cx) {
+      Poll::Ready(Some(())) => Poll::Ready(()),
+      _ => Poll::Pending,
+    }
+  }
+
+  /// Saves `model` for the next build to pick up, and, on the web,
+  /// reloads the page to load it.
+  pub fn reload(&self, model: &M) {
+    match (self.serialize)(model) {
+      Ok(state) => self.store.save(state),
+      Err(err) => {
+        tracing::error!(%err, "failed to serialize the model");
+      }
+    }
+
+    #[cfg(all(target_arch = "wasm32", feature = "web"))]
+    let _ = gloo::utils::window().location().reload();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    prelude::*,
+    testing::{
+      fixtures::{self, Counter, CounterMsg::Add},
+      TestApp,
+    },
+  };
+
+  #[test]
+  fn hot_reload_restores_the_model() {
+    let store = MemoryStore::default();
+
+    let build = |reloads| {
+      App::new_hot_reloadable(
+        HotReload::new(store.clone(), reloads),
+        || Counter { count: 0 },
+        fixtures::update,
+        fixtures::view,
+      )
+    };
+
+    let (trigger, reloads) = ReloadTrigger::new();
+
+    let mut app = TestApp::new(build(reloads));
+
+    app.dispatch(Add(1));
+    app.dispatch(Add(1));
+    app.run_until_idle();
+
+    trigger.reload();
+
+    futures::executor::block_on(app.into_inner().run_until_stopped());
+
+    let (_, reloads) = ReloadTrigger::new();
+
+    let app = TestApp::new(build(reloads));
+
+    assert_eq!(app.model().count, 2);
+    assert_eq!(app.render_to_string(), fixtures::rendered(2));
+  }
+}
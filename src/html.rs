@@ -1,14 +1,21 @@
 use crate::{
+  observable::IntoStream,
   prelude::DynChild,
   runtime::{Ctx, IntoMsg},
   view::{IntoView, View, ViewInner, ViewKind},
 };
-use futures::{SinkExt, Stream};
-use std::collections::{HashMap, HashSet};
+use futures::{stream::LocalBoxStream, FutureExt, StreamExt};
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+use std::collections::HashMap;
+use std::{
+  collections::{BTreeMap, HashSet},
+  fmt,
+};
 #[cfg(all(target_arch = "wasm32", feature = "web"))]
 use wasm_bindgen::JsValue;
 
 type ChildrenFn<'a, Msg> = Box<dyn FnOnce(Ctx<Msg>) -> View<Msg> + 'a>;
+type DynAttribute = (String, LocalBoxStream<'static, String>);
 type EventListener<Msg> =
   (String, Box<dyn FnMut(&web_sys::Event) -> Option<Msg>>);
 
@@ -26,7 +33,8 @@ pub trait HtmlElementMetadata {
 pub struct HtmlElement<'a, El, Msg = ()> {
   cx: Ctx<Msg>,
   kind: El,
-  attributes: HashMap<String, String>,
+  attributes: BTreeMap<String, String>,
+  dyn_attributes: Vec<DynAttribute>,
   #[cfg(all(target_arch = "wasm32", feature = "web"))]
   props: HashMap<String, JsValue>,
   #[cfg(all(target_arch = "wasm32", feature = "web"))]
@@ -42,6 +50,7 @@ impl<'a, El: HtmlElementMetadata, Msg: 'static> IntoView<Msg>
       cx,
       kind,
       attributes,
+      dyn_attributes,
       #[cfg(all(target_arch = "wasm32", feature = "web"))]
       props,
       #[cfg(all(target_arch = "wasm32", feature = "web"))]
@@ -49,6 +58,8 @@ impl<'a, El: HtmlElementMetadata, Msg: 'static> IntoView<Msg>
       children,
     } = self;
 
+    let _span = tracing::trace_span!("element", name = %kind.name()).entered();
+
     let mut kind = if kind.is_void() {
       ViewKind::new_void_html(
         &kind.name(),
@@ -65,6 +76,27 @@ impl<'a, El: HtmlElementMetadata, Msg: 'static> IntoView<Msg>
 
     kind.set_attributes(attributes);
 
+    let attribute_setter = kind.attribute_setter().unwrap();
+
+    for (name, mut values) in dyn_attributes {
+      // Observables yield their current value immediately, so it
+      // is available for the first render
+      if let Some(value) = values.next().now_or_never().flatten() {
+        attribute_setter.set(&name, value);
+      }
+
+      let task = cx.spawner.spawn_guarded(values.for_each(clone!(
+        [attribute_setter],
+        move |value| {
+          attribute_setter.set(&name, value);
+
+          async {}
+        }
+      )));
+
+      kind.own_task(task);
+    }
+
     #[cfg(all(target_arch = "wasm32", feature = "web"))]
     kind.set_event_listeners(|n| {
       event_listeners
@@ -76,12 +108,7 @@ impl<'a, El: HtmlElementMetadata, Msg: 'static> IntoView<Msg>
             let res = handler(e);
 
             if let Some(msg) = res {
-              wasm_bindgen_futures::spawn_local(clone!(
-                [mut dispatcher],
-                async move {
-                  let _ = dispatcher.send(msg).await;
-                }
-              ));
+              dispatcher.dispatch(msg);
             }
           })
         })
@@ -96,6 +123,8 @@ impl<'a, El: HtmlElementMetadata, Msg: 'static> IntoView<Msg>
       .into_iter()
       .map(|f| f(cx.clone()))
       .map(|mut child_view| {
+        child_view.attach_to(&cx);
+
         #[cfg(all(target_arch = "wasm32", feature = "web"))]
         {
           let child_node = child_view.0.kind.get_node();
@@ -125,6 +154,7 @@ impl<'a, El: HtmlElementMetadata, Msg: 'static> HtmlElement<'a, El, Msg> {
       cx,
       kind,
       attributes: Default::default(),
+      dyn_attributes: Default::default(),
       #[cfg(all(target_arch = "wasm32", feature = "web"))]
       props: Default::default(),
       #[cfg(all(target_arch = "wasm32", feature = "web"))]
@@ -149,6 +179,32 @@ impl<'a, El: HtmlElementMetadata, Msg: 'static> HtmlElement<'a, El, Msg> {
     self
   }
 
+  /// Sets the attribute `name` to each value yielded by `values`, such
+  /// as an [`Observable`](crate::prelude::Observable).
+  pub fn dyn_attr<S>(mut self, name: impl ToString, values: S) -> Self
+  where
+    S: IntoStream,
+    S::Item: ToString,
+  {
+    let values = values
+      .into_stream()
+      .map(|value| value.to_string())
+      .boxed_local();
+
+    self.dyn_attributes.push((name.to_string(), values));
+
+    self
+  }
+
+  /// Sets `data` as a context for this element and its children only,
+  /// shadowing any context of the same type set further up the view.
+  #[track_caller]
+  pub fn provide<T: 'static>(mut self, data: T) -> Self {
+    self.cx = self.cx.provide(data);
+
+    self
+  }
+
   pub fn class(mut self, name: impl ToString) -> Self {
     let classes = self.attributes.entry("class".to_string()).or_default();
 
@@ -186,25 +242,72 @@ impl<'a, El: HtmlElementMetadata, Msg: 'static> HtmlElement<'a, El, Msg> {
     self
   }
 
+  /// Adds a child view of a nested module with its own message type,
+  /// passing its messages through `f`.
+  pub fn map_child<ChildMsg, V>(
+    mut self,
+    f: impl Fn(ChildMsg) -> Msg + 'static,
+    child_fn: impl FnOnce(Ctx<ChildMsg>) -> V + 'a,
+  ) -> Self
+  where
+    ChildMsg: 'static,
+    V: IntoView<ChildMsg>,
+  {
+    self.children.push(Box::new(move |cx| {
+      let child = child_fn(cx.map(f)).into_view();
+
+      View(ViewInner {
+        cx,
+        kind: ViewKind::Mapped(Box::new(child)),
+      })
+    }));
+
+    self
+  }
+
   pub fn dyn_child<S, F, V>(mut self, stream: S, f: F) -> Self
   where
     Msg: 'static,
-    S: Stream + 'static,
+    S: IntoStream,
     F: FnMut(Ctx<Msg>, S::Item) -> V + 'static,
     V: IntoView<Msg>,
   {
-    let component = DynChild::new(self.cx.clone(), stream, f);
+    let stream = stream.into_stream();
+
+    self.children.push(Box::new(move |cx| {
+      DynChild::new(cx, stream, f).into_view()
+    }));
+
+    self
+  }
+
+  /// Like [`HtmlElement::dyn_child`], but building the child can also
+  /// return an error, which is shown by the enclosing
+  /// [`ErrorBoundary`](crate::prelude::ErrorBoundary).
+  pub fn try_dyn_child<S, F, V, E>(mut self, stream: S, f: F) -> Self
+  where
+    Msg: 'static,
+    S: IntoStream,
+    F: FnMut(Ctx<Msg>, S::Item) -> Result<V, E> + 'static,
+    V: IntoView<Msg>,
+    E: fmt::Display,
+  {
+    let stream = stream.into_stream();
 
-    self.children.push(Box::new(|_| component.into_view()));
+    self.children.push(Box::new(move |cx| {
+      DynChild::fallible(cx, stream, f).into_view()
+    }));
 
     self
   }
 
-  pub fn dyn_text<S, T>(mut self, stream: S) -> Self
+  pub fn dyn_text<S>(mut self, stream: S) -> Self
   where
-    S: Stream<Item = T> + 'static,
-    T: ToString,
+    S: IntoStream,
+    S::Item: ToString,
   {
+    let stream = stream.into_stream();
+
     self.children.push(Box::new(move |cx| {
       DynChild::new(cx, stream, |cx, t| text(cx, t)).into_view()
     }));
@@ -281,3 +384,86 @@ pub fn text<Msg>(cx: Ctx<Msg>, text: impl ToString) -> View<Msg> {
     kind: ViewKind::new_text(&text.to_string()),
   })
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+
+  #[test]
+  fn hello_world() {
+    enum Msg {
+      Increment,
+      Decrement,
+    }
+
+    let app = App::new(
+      || (),
+      |_, _| (),
+      |_, cx: Ctx<Msg>| {
+        div(cx)
+          .child(|cx| {
+            p(cx).attr("attr", "val").class("counter-class").text("0")
+          })
+          .child(|cx| {
+            div(cx)
+              .child(|cx| {
+                button(cx).text("-").on("click", |_| Some(Msg::Decrement))
+              })
+              .child(|cx| {
+                button(cx).text("+").on("click", |_| Some(Msg::Increment))
+              })
+          })
+      },
+    );
+
+    assert_eq!(
+      app.render_to_string(),
+      "<div><p attr=\"val\" \
+       class=\"counter-class\">0</p><div><button>-</button><button>+</\
+       button></div></div>"
+    )
+  }
+
+  #[test]
+  fn dropped_elements_stop_updating_their_attributes() {
+    use std::cell::RefCell;
+
+    #[derive(Clone)]
+    enum Msg {
+      Toggle,
+    }
+
+    #[derive(DiffableModel)]
+    struct Model {
+      shown: bool,
+    }
+
+    let (tx, rx) = futures::channel::mpsc::unbounded::<&str>();
+    let rx = RefCell::new(Some(rx));
+
+    let mut app = TestApp::new(App::new(
+      || Model { shown: true },
+      |model, Msg::Toggle| Model {
+        shown: !model.shown,
+      },
+      move |vm: &ModelViewModel, cx: Ctx<Msg>| {
+        let mut rx = rx.borrow_mut().take();
+
+        div(cx).dyn_child(&vm.shown, move |cx, shown| match rx.take() {
+          Some(rx) if shown => p(cx).dyn_attr("title", rx).into_view(),
+          _ => p(cx).into_view(),
+        })
+      },
+    ));
+
+    tx.unbounded_send("shown").unwrap();
+    app.run_until_idle();
+
+    assert!(app.render_to_string().contains("title=\"shown\""));
+
+    app.dispatch(Msg::Toggle);
+    app.run_until_idle();
+
+    assert!(tx.is_closed());
+  }
+}
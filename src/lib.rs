@@ -2,37 +2,104 @@
 
 #[macro_use]
 extern crate clone_macro;
+extern crate self as frappe_tea;
 
 #[macro_use]
 mod utils;
+mod cmd;
 mod components;
+#[cfg(feature = "devtools")]
+mod devtools;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
 mod html;
+mod lifecycle;
+mod middleware;
+mod observable;
+#[cfg(feature = "persistence")]
+mod persistence;
+mod priority;
+#[cfg(feature = "recording")]
+mod recording;
+mod resource;
 mod runtime;
+mod sender;
+mod sub;
+#[cfg(not(all(target_arch = "wasm32", feature = "web")))]
+pub mod testing;
+#[cfg(feature = "time-travel")]
+mod time_travel;
 mod view;
 
 pub mod prelude {
   use super::*;
 
   pub use super::App;
+  pub use cmd::Cmd;
   pub use components::*;
+  #[cfg(all(target_arch = "wasm32", feature = "web", feature = "devtools"))]
+  pub use devtools::ExtensionTransport;
+  #[cfg(feature = "devtools")]
+  pub use devtools::{DevTools, DevToolsTransport, MemoryTransport};
+  pub use frappe_tea_macros::DiffableModel;
+  #[cfg(all(
+    target_arch = "wasm32",
+    feature = "web",
+    feature = "hot-reload"
+  ))]
+  pub use hot_reload::{websocket_reloads, SessionStore};
+  #[cfg(feature = "hot-reload")]
+  pub use hot_reload::{HotReload, MemoryStore, ReloadTrigger, StateStore};
   pub use html::*;
-  pub use runtime::{Ctx, DiffableModel};
+  pub use lifecycle::AppHandle;
+  pub use middleware::Middleware;
+  pub use observable::{IntoStream, Observable};
+  #[cfg(all(not(target_arch = "wasm32"), feature = "persistence"))]
+  pub use persistence::FileStorage;
+  #[cfg(all(
+    target_arch = "wasm32",
+    feature = "web",
+    feature = "persistence"
+  ))]
+  pub use persistence::{LocalStorage, SessionStorage};
+  #[cfg(feature = "persistence")]
+  pub use persistence::{MemoryStorage, Persistence, Storage};
+  pub use priority::Priority;
+  #[cfg(feature = "recording")]
+  pub use recording::{
+    RecordingHandle, Session, SessionError, SessionFormat,
+  };
+  pub use resource::{Resource, ResourceState, ResourceStream};
+  pub use runtime::{
+    AnyReport, AsyncUpdate, AsyncUpdateFn, ContextError, Ctx, DiffableModel,
+    IntoUpdate, PendingPolicy, Scheduling, StopHandle, UpdateError,
+    VecViewModel,
+  };
+  pub use sender::{AppSender, SendError};
+  pub use sub::{Sub, SubId};
+  #[cfg(feature = "time-travel")]
+  pub use time_travel::TimeTravelHandle;
   pub use view::{IntoView, View};
 }
 
-use runtime::DiffableModel;
+use runtime::{DiffableModel, IntoUpdate};
+use std::{cell::RefCell, rc::Rc};
 use view::IntoView;
 
 /// Represents an app.
 pub struct App<M: DiffableModel, Msg, UF> {
   rt: runtime::Runtime<M, Msg, UF>,
   view: view::View<Msg>,
+  exit: Rc<RefCell<lifecycle::Exit<M>>>,
+  resources: Rc<resource::Tracker>,
 }
 
-impl<M, Msg, UF> App<M, Msg, UF>
+impl<M, Msg, UF, U> App<M, Msg, UF>
 where
   M: DiffableModel,
-  UF: FnMut(M, Msg) -> M,
+  Msg: 'static,
+  UF: FnMut(M, Msg) -> U,
+  U: IntoUpdate<M, Msg>,
 {
   pub fn new<V: IntoView<Msg>>(
     init_model: impl FnOnce() -> M,
@@ -45,71 +112,456 @@ where {
     let model = init_model();
     let view_model = model.to_view_model();
 
-    let cx = runtime::Ctx::new(tx);
+    let executor = utils::Executor::default();
+
+    let cx = runtime::Ctx::new(tx.clone(), executor.spawner());
+
+    if cx.set_context(resource::Tracker::default()).is_err() {
+      unreachable!("new contexts to be empty");
+    }
+
+    let resources = cx.get_context::<resource::Tracker>().unwrap();
 
     let view = view_fn(&view_model, cx).into_view();
 
-    let rt = runtime::Runtime::new(Some(model), view_model, update_fn, rx);
+    let rt = runtime::Runtime::new(
+      Some(model),
+      view_model,
+      update_fn,
+      tx,
+      rx,
+      executor,
+    );
+
+    Self {
+      rt,
+      view,
+      exit: Default::default(),
+      resources,
+    }
+  }
+
+  /// Creates an app whose model is saved with `persistence` as it
+  /// changes.
+  ///
+  /// The saved model, if any, is applied on top of the one returned by
+  /// `init_model` before the view is built, so the first render
+  /// already shows it.
+  #[cfg(feature = "persistence")]
+  pub fn new_persisted<V: IntoView<Msg>>(
+    persistence: persistence::Persistence<M>,
+    init_model: impl FnOnce() -> M,
+    update_fn: UF,
+    view_fn: impl FnOnce(&M::ViewModel, runtime::Ctx<Msg>) -> V,
+  ) -> Self {
+    let mut app =
+      Self::new(|| persistence.restore(init_model()), update_fn, view_fn);
+
+    app.rt.set_persistence(persistence);
+
+    app
+  }
+
+  /// Sets the function which determines the active [`Sub`]scriptions
+  /// for the current model.
+  ///
+  /// [`Sub`]: sub::Sub
+  pub fn subscriptions(
+    mut self,
+    f: impl Fn(&M) -> sub::Sub<Msg> + 'static,
+  ) -> Self {
+    self.rt.set_subscriptions_fn(Box::new(f));
+
+    self
+  }
+
+  /// Sets how often the view model is diffed.
+  ///
+  /// By default, it is diffed after every message.
+  pub fn scheduling(mut self, scheduling: runtime::Scheduling) -> Self {
+    self.rt.set_scheduling(scheduling);
+
+    self
+  }
+
+  /// Marks messages for which `f` returns `true` as urgent, causing the
+  /// view model to be diffed immediately, even when batching.
+  pub fn urgent_if(mut self, f: impl Fn(&Msg) -> bool + 'static) -> Self {
+    self.rt.set_urgent_fn(Box::new(f));
+
+    self
+  }
+
+  /// Sets the function which determines the [`Priority`] of each
+  /// message.
+  ///
+  /// By default, all messages are [`Priority::Normal`].
+  ///
+  /// [`Priority`]: priority::Priority
+  /// [`Priority::Normal`]: priority::Priority::Normal
+  pub fn priority(
+    mut self,
+    f: impl Fn(&Msg) -> priority::Priority + 'static,
+  ) -> Self {
+    self.rt.set_priority_fn(Box::new(f));
+
+    self
+  }
+
+  /// Sets the function which handles errors returned from the update
+  /// function, which can send a message in response.
+  ///
+  /// Without one, errors are logged.
+  pub fn on_update_error<IMsg>(
+    mut self,
+    mut f: impl FnMut(runtime::UpdateError<Msg>) -> IMsg + 'static,
+  ) -> Self
+  where
+    IMsg: runtime::IntoMsg<Msg>,
+  {
+    self
+      .rt
+      .set_update_error_fn(Box::new(move |err| f(err).into_msg()));
+
+    self
+  }
+
+  /// Sets the function which decides what happens to a pending
+  /// [`AsyncUpdate`] when another message arrives, given the message
+  /// of the pending update and the new one.
+  ///
+  /// By default, new messages wait for the update to finish.
+  ///
+  /// [`AsyncUpdate`]: runtime::AsyncUpdate
+  pub fn pending_policy(
+    mut self,
+    f: impl Fn(&Msg, &Msg) -> runtime::PendingPolicy + 'static,
+  ) -> Self {
+    self.rt.set_pending_policy_fn(Box::new(f));
 
-    Self { rt, view }
+    self
   }
 
-  /// Runs the app.
+  /// Adds `middleware` around every update, after the middleware which
+  /// was already added.
+  pub fn middleware(
+    mut self,
+    middleware: impl middleware::Middleware<M, Msg> + 'static,
+  ) -> Self
+  where
+    M: Clone,
+    Msg: Clone,
+  {
+    self.rt.add_middleware(Box::new(middleware));
+
+    self
+  }
+
+  /// Mounts and runs the app, until it is shut down with an
+  /// [`AppHandle`](lifecycle::AppHandle).
+  ///
+  /// The view is then removed from `mount_target_node`.
   #[cfg(all(target_arch = "wasm32", feature = "web"))]
-  pub async fn run(&mut self, mount_target_node: &web_sys::Node) -> ! {
+  pub async fn run(mut self, mount_target_node: &web_sys::Node) {
     let root_node = self.view.0.kind.get_node();
 
     mount_target_node
       .append_child(&root_node)
       .expect("mounting to succeed");
 
+    self.rt.run().await;
+
+    mount_target_node
+      .remove_child(&root_node)
+      .expect("unmounting to succeed");
+  }
+
+  /// Runs the app until it is shut down with an
+  /// [`AppHandle`](lifecycle::AppHandle).
+  ///
+  /// Timers used by the app need this to be called from within a tokio
+  /// runtime.
+  ///
+  /// Effects, subscriptions and view tasks are `!Send`, but they are
+  /// driven by the returned future itself rather than being spawned,
+  /// so no `tokio::task::LocalSet` is needed.
+  #[cfg(all(not(target_arch = "wasm32"), feature = "ssr"))]
+  pub async fn run(mut self) {
     self.rt.run().await
   }
 
+  /// Runs the app until it is stopped, without mounting it.
+  ///
+  /// Effects and subscriptions are driven by the returned future
+  /// itself, so this can be used with any executor.
+  ///
+  /// Once stopped, the final model is handed over to
+  /// [`AppHandle::unmount`](lifecycle::AppHandle::unmount), while the
+  /// view stays around until the app is dropped.
+  pub async fn run_until_stopped(&mut self) {
+    self.rt.run_until_stopped().await;
+
+    // Hot-reloading also ends the loop, without the app being done
+    if self.rt.stop_handle().is_stopped() {
+      self.exit.borrow_mut().finish(self.rt.take_model());
+    }
+  }
+
+  /// Gets a handle which can be used to stop the app.
+  pub fn stop_handle(&self) -> runtime::StopHandle<Msg> {
+    self.rt.stop_handle()
+  }
+
+  /// Gets a sender which can be used to send messages to the app
+  /// from outside of its view.
+  pub fn sender(&self) -> sender::AppSender<Msg> {
+    sender::AppSender::new(self.rt.msg_sender().clone(), None)
+  }
+
+  /// Like [`App::sender`], but at most `capacity` messages sent
+  /// through it can be waiting for the runtime at any given time.
+  ///
+  /// Messages are only taken from its queue once the runtime is done
+  /// with every other message, so [`AppSender::send`] waits for as
+  /// long as the app is busy.
+  ///
+  /// [`AppSender::send`]: sender::AppSender::send
+  pub fn bounded_sender(&mut self, capacity: usize) -> sender::AppSender<Msg> {
+    let (tx, rx) = futures::channel::mpsc::channel(capacity);
+
+    self.rt.add_bounded_receiver(rx);
+
+    sender::AppSender::new(self.rt.msg_sender().clone(), Some(tx))
+  }
+
+  /// Gets a handle which can be used to tear down the app once it
+  /// is running.
+  pub fn handle(&self) -> lifecycle::AppHandle<M, Msg> {
+    lifecycle::AppHandle::new(self.rt.stop_handle(), self.exit.clone())
+  }
+
   /// Renders the app to a [`String`].
+  ///
+  /// Pending view tasks are polled first, so dynamic views show their
+  /// latest values.
   #[cfg(feature = "ssr")]
   pub fn render_to_string(&self) -> String {
+    self.rt.poll_tasks();
+
     self.view.to_string()
   }
+
+  /// Waits for every [`Resource`](resource::Resource) of the view to
+  /// finish loading, so it is rendered with their data by
+  /// [`App::render_to_string`].
+  #[cfg(feature = "ssr")]
+  pub async fn resources_ready(&self) {
+    futures::future::poll_fn(|cx| {
+      // Views re-render in these tasks, which might start loading more
+      // resources
+      self.rt.poll_tasks_with(cx);
+
+      if self.resources.is_loading() {
+        std::task::Poll::Pending
+      } else {
+        std::task::Poll::Ready(())
+      }
+    })
+    .await
+  }
+}
+
+impl<M, Msg> App<M, Msg, runtime::AsyncUpdateFn<M, Msg>>
+where
+  M: DiffableModel + Clone + 'static,
+  Msg: Clone + 'static,
+{
+  /// Creates an app whose update function is asynchronous.
+  ///
+  /// Update functions which are only sometimes asynchronous can
+  /// instead return an [`AsyncUpdate`](runtime::AsyncUpdate) from
+  /// [`App::new`].
+  pub fn new_async<F, U, V>(
+    init_model: impl FnOnce() -> M,
+    mut update_fn: impl FnMut(M, Msg) -> F + 'static,
+    view_fn: impl FnOnce(&M::ViewModel, runtime::Ctx<Msg>) -> V,
+  ) -> Self
+  where
+    F: std::future::Future<Output = U> + 'static,
+    U: IntoUpdate<M, Msg>,
+    V: IntoView<Msg>,
+  {
+    Self::new(
+      init_model,
+      Box::new(move |model, msg| {
+        runtime::AsyncUpdate::new(update_fn(model, msg))
+      }),
+      view_fn,
+    )
+  }
+}
+
+/// Hands the final model to [`AppHandle::unmount`], after which the
+/// view, along with its event listeners and dynamic children, is
+/// dropped.
+///
+/// [`AppHandle::unmount`]: lifecycle::AppHandle::unmount
+impl<M: DiffableModel, Msg, UF> Drop for App<M, Msg, UF> {
+  fn drop(&mut self) {
+    self.exit.borrow_mut().finish(self.rt.take_model());
+  }
+}
+
+#[cfg(feature = "hot-reload")]
+impl<M, Msg, UF, U> App<M, Msg, UF>
+where
+  M: DiffableModel + serde::Serialize + serde::de::DeserializeOwned,
+  Msg: 'static,
+  UF: FnMut(M, Msg) -> U,
+  U: IntoUpdate<M, Msg>,
+{
+  /// Creates an app which carries its model over to new builds.
+  ///
+  /// If a previous build saved its model, it is restored instead of
+  /// calling `init_model`. Once a new build is available, the model is
+  /// saved and the app stops; on the web, the page is then reloaded.
+  pub fn new_hot_reloadable<V: IntoView<Msg>>(
+    hot_reload: hot_reload::HotReload,
+    init_model: impl FnOnce() -> M,
+    update_fn: UF,
+    view_fn: impl FnOnce(&M::ViewModel, runtime::Ctx<Msg>) -> V,
+  ) -> Self {
+    let restored = hot_reload.restore();
+
+    let mut app =
+      Self::new(|| restored.unwrap_or_else(init_model), update_fn, view_fn);
+
+    app.rt.set_reloader(hot_reload.into_reloader());
+
+    app
+  }
+}
+
+#[cfg(feature = "time-travel")]
+impl<M, Msg, UF> App<M, Msg, UF>
+where
+  M: DiffableModel + Clone,
+  Msg: Clone + 'static,
+{
+  /// Records every processed message, along with periodic snapshots of
+  /// the model, so the app can be rewound and replayed with a
+  /// [`TimeTravelHandle`](time_travel::TimeTravelHandle).
+  ///
+  /// Async updates are not run again when replaying, as they might
+  /// resolve differently, so the models they resolved to are reused.
+  pub fn time_travel(mut self) -> Self {
+    self.rt.enable_time_travel();
+
+    self
+  }
+
+  /// Gets a handle for controlling the recorded history, if
+  /// [`App::time_travel`] was called.
+  pub fn time_travel_handle(
+    &self,
+  ) -> Option<time_travel::TimeTravelHandle<Msg>> {
+    self.rt.time_travel_handle()
+  }
+}
+
+#[cfg(feature = "devtools")]
+impl<M, Msg, UF> App<M, Msg, UF>
+where
+  M: DiffableModel + Clone + 'static,
+  Msg: Clone + 'static,
+{
+  /// Reports every processed message, and the resulting model, to the
+  /// Redux DevTools, which can jump to and skip them through
+  /// time-travel.
+  ///
+  /// Time-travel is enabled if [`App::time_travel`] was not called.
+  pub fn devtools(mut self, devtools: devtools::DevTools<M, Msg>) -> Self {
+    if self.rt.time_travel_handle().is_none() {
+      self.rt.enable_time_travel();
+    }
+
+    let history = self.rt.time_travel_handle().unwrap();
+
+    let bridge = devtools.connect(self.rt.model(), history);
+
+    self.rt.add_middleware(Box::new(bridge));
+
+    self
+  }
+}
+
+#[cfg(feature = "recording")]
+impl<M, Msg, UF> App<M, Msg, UF>
+where
+  M: DiffableModel + Clone,
+  Msg: Clone + 'static,
+{
+  /// Records the model the app starts running with, and every message
+  /// it receives, so the session can be exported with a
+  /// [`RecordingHandle`](recording::RecordingHandle) and replayed with
+  /// [`TestApp::replay`](testing::TestApp::replay).
+  pub fn record(mut self) -> Self {
+    self.rt.enable_recording();
+
+    self
+  }
+
+  /// Gets a handle for exporting the recorded session, if
+  /// [`App::record`] was called.
+  pub fn recording_handle(
+    &self,
+  ) -> Option<recording::RecordingHandle<M, Msg>> {
+    self.rt.recording_handle()
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
-  use prelude::*;
 
-  #[test]
-  fn hello_world() {
-    enum Msg {
-      Increment,
-      Decrement,
-    }
+  #[cfg(all(target_arch = "wasm32", feature = "web"))]
+  wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
 
-    let app = App::new(
-      || (),
-      |_, _| (),
-      |_, cx: Ctx<Msg>| {
-        div(cx)
-          .child(|cx| {
-            p(cx).attr("attr", "val").class("counter-class").text("0")
-          })
-          .child(|cx| {
-            div(cx)
-              .child(|cx| {
-                button(cx).text("-").on("click", |_| Some(Msg::Decrement))
-              })
-              .child(|cx| {
-                button(cx).text("+").on("click", |_| Some(Msg::Increment))
-              })
-          })
-      },
-    );
+  #[cfg(all(target_arch = "wasm32", feature = "web"))]
+  #[wasm_bindgen_test::wasm_bindgen_test]
+  async fn shut_down_apps_are_removed_from_the_dom() {
+    use prelude::*;
 
-    assert_eq!(
-      app.render_to_string(),
-      "<div><p attr=\"val\" \
-       class=\"counter-class\">0</p><div><button>-</button><button>+</\
-       button></div></div>"
-    )
+    let mount_target = gloo::utils::document().create_element("div").unwrap();
+
+    let app = App::new(|| (), |_, ()| (), |_, cx: Ctx<()>| p(cx).text("hi"));
+
+    let handle = app.handle();
+
+    let ((), _) = futures::join!(app.run(&mount_target), async {
+      assert_eq!(mount_target.inner_html(), "<p>hi</p>");
+
+      handle.unmount().await
+    });
+
+    assert_eq!(mount_target.inner_html(), "");
+  }
+
+  #[cfg(feature = "ssr")]
+  #[tokio::test(flavor = "multi_thread")]
+  async fn apps_run_under_tokio_without_a_local_set() {
+    use testing::fixtures::{self, CounterMsg::Add};
+
+    let mut app = fixtures::counter(0);
+
+    let sender = app.sender();
+
+    sender.try_send(Add(1)).unwrap();
+    sender.try_send(Add(1)).unwrap();
+    app.stop_handle().stop();
+
+    app.run_until_stopped().await;
+
+    assert_eq!(app.render_to_string(), fixtures::rendered(2));
   }
 }
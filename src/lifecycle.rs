@@ -0,0 +1,167 @@
+//! Tearing down a running app.
+
+use crate::runtime::StopHandle;
+use futures::future;
+use std::{
+  cell::RefCell,
+  rc::Rc,
+  task::{Poll, Waker},
+};
+
+/// What is left of an app once it was torn down, shared between the
+/// app and its handles.
+pub(crate) struct Exit<M> {
+  /// The final model, until an [`AppHandle::unmount`] takes it.
+  model: Option<M>,
+  done: bool,
+  wakers: Vec<Waker>,
+}
+
+impl<M> Default for Exit<M> {
+  fn default() -> Self {
+    Self {
+      model: None,
+      done: false,
+      wakers: vec![],
+    }
+  }
+}
+
+impl<M> Exit<M> {
+  /// Marks the app as torn down, handing `model` to whoever is
+  /// waiting for it.
+  pub fn finish(&mut self, model: Option<M>) {
+    if self.done {
+      return;
+    }
+
+    self.model = model;
+    self.done = true;
+
+    for waker in self.wakers.drain(..) {
+      waker.wake();
+    }
+  }
+}
+
+/// A handle for tearing down a running [`App`](crate::App), such as
+/// when the route showing a micro-frontend is left.
+///
+/// Clones control the same app.
+#[derive(educe::Educe)]
+#[educe(Clone)]
+pub struct AppHandle<M, Msg> {
+  stop_handle: StopHandle<Msg>,
+  exit: Rc<RefCell<Exit<M>>>,
+}
+
+impl<M, Msg> AppHandle<M, Msg> {
+  pub(crate) fn new(
+    stop_handle: StopHandle<Msg>,
+    exit: Rc<RefCell<Exit<M>>>,
+  ) -> Self {
+    Self { stop_handle, exit }
+  }
+
+  /// Stops the message loop, without waiting for the app to be torn
+  /// down.
+  ///
+  /// Messages which were already queued are still processed, after
+  /// which `App::run` resolves and the view is removed.
+  pub fn shutdown(&self) {
+    self.stop_handle.stop();
+  }
+
+  /// Returns `true` once the app was torn down.
+  pub fn is_shut_down(&self) -> bool {
+    self.exit.borrow().done
+  }
+
+  /// Shuts the app down, and waits for its view, along with its event
+  /// listeners and dynamic children, to be removed.
+  ///
+  /// Resolves to the final model, unless another handle already took
+  /// it.
+  pub async fn unmount(&self) -> Option<M> {
+    self.shutdown();
+
+    future::poll_fn(|cx| {
+      let mut exit = self.exit.borrow_mut();
+
+      if exit.done {
+        Poll::Ready(exit.model.take())
+      } else {
+        exit.wakers.push(cx.waker().clone());
+
+        Poll::Pending
+      }
+    })
+    .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    prelude::*,
+    testing::fixtures::{self, Counter, CounterMsg, CounterViewModel},
+  };
+
+  #[test]
+  fn unmounting_stops_the_app_and_returns_the_model() {
+    use std::{cell::RefCell, rc::Rc};
+
+    let view_cx = Rc::new(RefCell::new(None));
+
+    let app = App::new(
+      || Counter { count: 0 },
+      fixtures::update,
+      clone!([view_cx], move |vm: &CounterViewModel, cx: Ctx<_>| {
+        *view_cx.borrow_mut() = Some(cx.clone());
+
+        fixtures::view(vm, cx)
+      }),
+    );
+
+    let handle = app.handle();
+
+    for _ in 0..3 {
+      view_cx
+        .borrow()
+        .as_ref()
+        .unwrap()
+        .msg_dispatcher
+        .dispatch(CounterMsg::Add(1));
+    }
+
+    assert!(!handle.is_shut_down());
+
+    let ((), model) = tokio::runtime::Builder::new_current_thread()
+      .enable_time()
+      .build()
+      .unwrap()
+      .block_on(async { futures::join!(app.run(), handle.unmount()) });
+
+    // Queued messages are still processed
+    assert_eq!(model.map(|model| model.count), Some(3));
+    assert!(handle.is_shut_down());
+    assert!(futures::executor::block_on(handle.unmount()).is_none());
+  }
+
+  #[test]
+  fn unmount_resolves_once_the_loop_stops() {
+    let mut app = fixtures::counter(0);
+
+    let handle = app.handle();
+
+    app.sender().try_send(CounterMsg::Add(1)).ok().unwrap();
+
+    let ((), model) = futures::executor::block_on(async {
+      futures::join!(app.run_until_stopped(), handle.unmount())
+    });
+
+    assert_eq!(model.map(|model| model.count), Some(1));
+    assert!(handle.is_shut_down());
+    assert_eq!(app.render_to_string(), fixtures::rendered(1));
+  }
+}
@@ -0,0 +1,179 @@
+/// Behaviour which wraps every update of an app, such as logging,
+/// analytics, or permission checks.
+pub trait Middleware<M, Msg> {
+  /// Called before `msg` is applied to `model`.
+  ///
+  /// Returning `None` drops the message, skipping the update and the
+  /// remaining middleware. A different message can also be returned
+  /// in its place.
+  fn before_update(&mut self, model: &M, msg: Msg) -> Option<Msg> {
+    let _ = model;
+
+    Some(msg)
+  }
+
+  /// Called after `msg` was applied to `old_model`, with the
+  /// resulting `model`.
+  fn after_update(&mut self, msg: &Msg, old_model: &M, model: &M) {
+    let _ = (msg, old_model, model);
+  }
+
+  /// Called after time-travel replaced the model with `model`, such
+  /// as when jumping to a past step.
+  #[cfg(feature = "time-travel")]
+  fn after_time_travel(&mut self, model: &M) {
+    let _ = model;
+  }
+
+  /// Called when a step is toggled during time-travel, with `models`
+  /// holding the recomputed model after each step from `first` on.
+  ///
+  /// This is called before [`Middleware::after_time_travel`].
+  #[cfg(feature = "time-travel")]
+  fn after_toggle(&mut self, first: usize, models: &[M]) {
+    let _ = (first, models);
+  }
+}
+
+/// The middleware of an app, in the order they were added.
+pub(crate) struct Pipeline<M, Msg> {
+  stack: Vec<Box<dyn Middleware<M, Msg>>>,
+  clone_model: fn(&M) -> M,
+  clone_msg: fn(&Msg) -> Msg,
+}
+
+impl<M, Msg> Pipeline<M, Msg> {
+  pub fn new() -> Self
+  where
+    M: Clone,
+    Msg: Clone,
+  {
+    Self {
+      stack: vec![],
+      clone_model: M::clone,
+      clone_msg: Msg::clone,
+    }
+  }
+
+  pub fn push(&mut self, middleware: Box<dyn Middleware<M, Msg>>) {
+    self.stack.push(middleware);
+  }
+
+  pub fn clone_msg(&self, msg: &Msg) -> Msg {
+    (self.clone_msg)(msg)
+  }
+
+  /// Copies the model and message, so the old model is still around
+  /// once the update consumed it.
+  pub fn backup(&self, model: &M, msg: &Msg) -> (M, Msg) {
+    ((self.clone_model)(model), (self.clone_msg)(msg))
+  }
+
+  /// Passes `msg` through each middleware, starting with the first one
+  /// added.
+  pub fn before_update(&mut self, model: &M, msg: Msg) -> Option<Msg> {
+    self
+      .stack
+      .iter_mut()
+      .try_fold(msg, |msg, middleware| middleware.before_update(model, msg))
+  }
+
+  /// Notifies each middleware, starting with the last one added, so
+  /// that the first one added wraps all the others.
+  pub fn after_update(&mut self, msg: &Msg, old_model: &M, model: &M) {
+    for middleware in self.stack.iter_mut().rev() {
+      middleware.after_update(msg, old_model, model);
+    }
+  }
+
+  #[cfg(feature = "time-travel")]
+  pub fn after_time_travel(&mut self, model: &M) {
+    for middleware in self.stack.iter_mut().rev() {
+      middleware.after_time_travel(model);
+    }
+  }
+
+  #[cfg(feature = "time-travel")]
+  pub fn after_toggle(&mut self, first: usize, models: &[M]) {
+    for middleware in self.stack.iter_mut().rev() {
+      middleware.after_toggle(first, models);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+
+  #[test]
+  fn middleware_wraps_updates() {
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Clone, DiffableModel)]
+    struct Model {
+      count: i32,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Add(i32),
+      Double,
+      Reset,
+    }
+
+    struct Rewrite;
+
+    impl Middleware<Model, Msg> for Rewrite {
+      fn before_update(&mut self, model: &Model, msg: Msg) -> Option<Msg> {
+        match msg {
+          Msg::Double => Some(Msg::Add(model.count)),
+          // Only allowed once the count is high enough
+          Msg::Reset if model.count < 10 => None,
+          msg => Some(msg),
+        }
+      }
+    }
+
+    struct Log(Rc<RefCell<Vec<(Msg, i32, i32)>>>);
+
+    impl Middleware<Model, Msg> for Log {
+      fn after_update(&mut self, msg: &Msg, old_model: &Model, model: &Model) {
+        self
+          .0
+          .borrow_mut()
+          .push((msg.clone(), old_model.count, model.count));
+      }
+    }
+
+    let log = Rc::new(RefCell::new(vec![]));
+
+    let mut app = TestApp::new(
+      App::new(
+        || Model { count: 1 },
+        |Model { count }, msg| match msg {
+          Msg::Add(n) => Model { count: count + n },
+          Msg::Double => unreachable!(),
+          Msg::Reset => Model { count: 0 },
+        },
+        |_, cx: Ctx<Msg>| div(cx),
+      )
+      .middleware(Rewrite)
+      .middleware(Log(log.clone())),
+    );
+
+    for msg in [Msg::Reset, Msg::Double, Msg::Add(9), Msg::Reset] {
+      app.dispatch(msg);
+    }
+
+    app.run_until_idle();
+
+    assert_eq!(
+      *log.borrow(),
+      [
+        (Msg::Add(1), 1, 2),
+        (Msg::Add(9), 2, 11),
+        (Msg::Reset, 11, 0)
+      ]
+    );
+  }
+}
@@ -0,0 +1,160 @@
+use futures::{
+  channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+  Stream,
+};
+use std::{cell::RefCell, rc::Rc};
+
+/// A value inside a view model which notifies its subscribers
+/// whenever it is set.
+///
+/// Cloning an [`Observable`] yields a handle to the same value.
+#[derive(educe::Educe)]
+#[educe(Clone)]
+pub struct Observable<T>(Rc<RefCell<ObservableInner<T>>>);
+
+struct ObservableInner<T> {
+  value: T,
+  subscribers: Vec<UnboundedSender<T>>,
+}
+
+impl<T: Default + Clone> Default for Observable<T> {
+  fn default() -> Self {
+    Self::new(T::default())
+  }
+}
+
+impl<T: Clone> Observable<T> {
+  pub fn new(value: T) -> Self {
+    Self(Rc::new(RefCell::new(ObservableInner {
+      value,
+      subscribers: vec![],
+    })))
+  }
+
+  /// Gets a copy of the current value.
+  pub fn get(&self) -> T {
+    self.0.borrow().value.clone()
+  }
+
+  /// Calls `f` with a reference to the current value.
+  pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+    f(&self.0.borrow().value)
+  }
+
+  /// Sets the value, notifying all subscribers.
+  pub fn set(&self, value: T) {
+    let mut inner = self.0.borrow_mut();
+
+    inner
+      .subscribers
+      .retain(|subscriber| subscriber.unbounded_send(value.clone()).is_ok());
+
+    inner.value = value;
+  }
+
+  /// Sets the value only if it is different from the current one,
+  /// returning `true` if subscribers were notified.
+  pub fn set_if_changed(&self, value: &T) -> bool
+  where
+    T: PartialEq,
+  {
+    if self.0.borrow().value != *value {
+      self.set(value.clone());
+
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Returns a [`Stream`] which yields the current value, followed by
+  /// every value this observable is set to.
+  pub fn stream(&self) -> UnboundedReceiver<T> {
+    let (tx, rx) = mpsc::unbounded();
+
+    let mut inner = self.0.borrow_mut();
+
+    tx.unbounded_send(inner.value.clone()).unwrap();
+
+    inner.subscribers.push(tx);
+
+    rx
+  }
+}
+
+/// Types which can be turned into a [`Stream`] of values to be
+/// rendered, such as [`Observable`]s and any other [`Stream`].
+pub trait IntoStream {
+  type Item;
+  type Stream: Stream<Item = Self::Item> + 'static;
+
+  fn into_stream(self) -> Self::Stream;
+}
+
+impl<S: Stream + 'static> IntoStream for S {
+  type Item = S::Item;
+  type Stream = S;
+
+  fn into_stream(self) -> Self::Stream {
+    self
+  }
+}
+
+impl<T: Clone + 'static> IntoStream for Observable<T> {
+  type Item = T;
+  type Stream = UnboundedReceiver<T>;
+
+  fn into_stream(self) -> Self::Stream {
+    self.stream()
+  }
+}
+
+impl<T: Clone + 'static> IntoStream for &Observable<T> {
+  type Item = T;
+  type Stream = UnboundedReceiver<T>;
+
+  fn into_stream(self) -> Self::Stream {
+    self.stream()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+
+  #[test]
+  fn observables_rerender_view() {
+    #[derive(Clone)]
+    enum Msg {
+      Rename(String),
+    }
+
+    #[derive(DiffableModel)]
+    struct Model {
+      name: String,
+    }
+
+    let mut app = TestApp::new(App::new(
+      || Model {
+        name: "world".to_string(),
+      },
+      |_, Msg::Rename(name)| Model { name },
+      |vm: &ModelViewModel, cx: Ctx<Msg>| {
+        p(cx).dyn_attr("title", &vm.name).dyn_text(&vm.name)
+      },
+    ));
+
+    assert_eq!(
+      app.render_to_string(),
+      "<p title=\"world\"><!-- <DynChild> -->world<!-- </DynChild> --></p>"
+    );
+
+    app.dispatch(Msg::Rename("frappe".to_string()));
+    app.run_until_idle();
+
+    assert_eq!(
+      app.render_to_string(),
+      "<p title=\"frappe\"><!-- <DynChild> -->frappe<!-- </DynChild> --></p>"
+    );
+  }
+}
@@ -0,0 +1,472 @@
+//! Saving the model to storage, so it survives reloads.
+
+use crate::utils;
+use futures::{future::LocalBoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+  cell::RefCell,
+  collections::{BTreeMap, HashMap},
+  rc::Rc,
+  task::{self, Poll},
+  time::Duration,
+};
+
+/// A key-value store which persisted models are saved to.
+pub trait Storage {
+  /// Loads the value saved under `key`, if any.
+  fn load(&self, key: &str) -> Option<String>;
+
+  /// Saves `value` under `key`, replacing any previous value.
+  fn save(&self, key: &str, value: &str);
+}
+
+/// Keeps values in memory, such as for tests.
+///
+/// Clones share the same values.
+#[derive(Clone, Default)]
+pub struct MemoryStorage(Rc<RefCell<HashMap<String, String>>>);
+
+impl Storage for MemoryStorage {
+  fn load(&self, key: &str) -> Option<String> {
+    self.0.borrow().get(key).cloned()
+  }
+
+  fn save(&self, key: &str, value: &str) {
+    self.0.borrow_mut().insert(key.to_owned(), value.to_owned());
+  }
+}
+
+/// Keeps each value in a `{key}.json` file within a directory.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStorage {
+  dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStorage {
+  /// Stores values in `dir`, which is created on the first save.
+  pub fn new(dir: impl Into<std::path::PathBuf>) -> Self {
+    Self { dir: dir.into() }
+  }
+
+  fn path(&self, key: &str) -> std::path::PathBuf {
+    self.dir.join(format!("{key}.json"))
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Storage for FileStorage {
+  fn load(&self, key: &str) -> Option<String> {
+    std::fs::read_to_string(self.path(key)).ok()
+  }
+
+  fn save(&self, key: &str, value: &str) {
+    let res = std::fs::create_dir_all(&self.dir)
+      .and_then(|_| std::fs::write(self.path(key), value));
+
+    if let Err(err) = res {
+      tracing::error!(%err, key, "failed to save the model");
+    }
+  }
+}
+
+/// Keeps values in `localStorage`.
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+pub struct LocalStorage;
+
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+impl Storage for LocalStorage {
+  fn load(&self, key: &str) -> Option<String> {
+    use gloo::storage::Storage;
+
+    gloo::storage::LocalStorage::raw()
+      .get_item(key)
+      .ok()
+      .flatten()
+  }
+
+  fn save(&self, key: &str, value: &str) {
+    use gloo::storage::Storage;
+
+    let _ = gloo::storage::LocalStorage::raw().set_item(key, value);
+  }
+}
+
+/// Keeps values in `sessionStorage`.
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+pub struct SessionStorage;
+
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+impl Storage for SessionStorage {
+  fn load(&self, key: &str) -> Option<String> {
+    use gloo::storage::Storage;
+
+    gloo::storage::SessionStorage::raw()
+      .get_item(key)
+      .ok()
+      .flatten()
+  }
+
+  fn save(&self, key: &str, value: &str) {
+    use gloo::storage::Storage;
+
+    let _ = gloo::storage::SessionStorage::raw().set_item(key, value);
+  }
+}
+
+type SaveFn<M> = Box<dyn Fn(&M) -> serde_json::Result<Value>>;
+type RestoreFn<M> = Box<dyn Fn(Value, M) -> M>;
+type Migration = Box<dyn Fn(Value) -> Value>;
+
+/// Configures how the model of an app is persisted.
+///
+/// The model is saved once no messages were processed for a while, or
+/// at the latest once the maximum wait has passed, and restored when
+/// the app is created.
+///
+/// ```no_run
+/// # use frappe_tea::prelude::*;
+/// # use serde_json::json;
+/// # #[derive(DiffableModel, serde::Serialize, serde::Deserialize)]
+/// # struct Model {
+/// #   todos: Vec<String>,
+/// #   filter: String,
+/// # }
+/// # fn init() -> Model {
+/// #   Model { todos: vec![], filter: "all".to_string() }
+/// # }
+/// # fn update(model: Model, _: ()) -> Model {
+/// #   model
+/// # }
+/// # fn view(_: &ModelViewModel, cx: Ctx<()>) -> impl IntoView<()> {
+/// #   div(cx)
+/// # }
+/// # #[cfg(all(target_arch = "wasm32", feature = "web"))]
+/// let app = App::new_persisted(
+///   Persistence::new(LocalStorage, "todos")
+///     .version(2)
+///     // Version 1 stored a single list of titles
+///     .migration(1, |titles| json!({ "todos": titles, "filter": "all" })),
+///   init,
+///   update,
+///   view,
+/// );
+/// ```
+pub struct Persistence<M> {
+  storage: Box<dyn Storage>,
+  key: String,
+  version: u32,
+  /// Each migration turns a model saved with its version into the
+  /// shape used by the next version.
+  migrations: BTreeMap<u32, Migration>,
+  debounce: Duration,
+  max_wait: Duration,
+  save_fn: SaveFn<M>,
+  restore_fn: RestoreFn<M>,
+  /// Resolves when the model should be saved.
+  pending: Option<LocalBoxFuture<'static, ()>>,
+  /// Resolves once the oldest unsaved change waited for
+  /// [`Persistence::max_wait`].
+  deadline: Option<LocalBoxFuture<'static, ()>>,
+}
+
+impl<M: 'static> Persistence<M> {
+  /// Persists the whole model under `key`.
+  pub fn new(storage: impl Storage + 'static, key: impl ToString) -> Self
+  where
+    M: Serialize + DeserializeOwned,
+  {
+    Self::from_fns(
+      storage,
+      key,
+      Box::new(|model| serde_json::to_value(model)),
+      Box::new(|saved, model| restore_or_keep(saved, model, |saved, _| saved)),
+    )
+  }
+
+  /// Persists only the part of the model returned by `project`.
+  ///
+  /// When restoring, `restore` is called with the saved part and the
+  /// initial model.
+  pub fn projected<P, PF, RF>(
+    storage: impl Storage + 'static,
+    key: impl ToString,
+    project: PF,
+    restore: RF,
+  ) -> Self
+  where
+    P: Serialize + DeserializeOwned,
+    PF: Fn(&M) -> P + 'static,
+    RF: Fn(P, M) -> M + 'static,
+  {
+    Self::from_fns(
+      storage,
+      key,
+      Box::new(move |model| serde_json::to_value(project(model))),
+      Box::new(move |saved, model| restore_or_keep(saved, model, &restore)),
+    )
+  }
+
+  fn from_fns(
+    storage: impl Storage + 'static,
+    key: impl ToString,
+    save_fn: SaveFn<M>,
+    restore_fn: RestoreFn<M>,
+  ) -> Self {
+    Self {
+      storage: Box::new(storage),
+      key: key.to_string(),
+      version: 1,
+      migrations: Default::default(),
+      debounce: Duration::from_millis(500),
+      max_wait: Duration::from_secs(5),
+      save_fn,
+      restore_fn,
+      pending: None,
+      deadline: None,
+    }
+  }
+
+  /// Sets the version of the saved shape, which starts at `1`.
+  ///
+  /// Models saved with older versions are upgraded with the
+  /// registered migrations, and discarded if one is missing.
+  pub fn version(mut self, version: u32) -> Self {
+    self.version = version;
+
+    self
+  }
+
+  /// Registers `f` to turn models saved with version `from` into the
+  /// shape used by version `from + 1`.
+  pub fn migration(
+    mut self,
+    from: u32,
+    f: impl Fn(Value) -> Value + 'static,
+  ) -> Self {
+    self.migrations.insert(from, Box::new(f));
+
+    self
+  }
+
+  /// Sets how long to wait after the last message before saving.
+  ///
+  /// Defaults to 500ms.
+  pub fn debounce(mut self, debounce: Duration) -> Self {
+    self.debounce = debounce;
+
+    self
+  }
+
+  /// Sets how long a change can wait to be saved, when messages keep
+  /// delaying the save.
+  ///
+  /// Defaults to 5s.
+  pub fn max_wait(mut self, max_wait: Duration) -> Self {
+    self.max_wait = max_wait;
+
+    self
+  }
+}
+
+impl<M> Persistence<M> {
+  /// Applies the saved model, if any, on top of `model`.
+  pub(crate) fn restore(&self, model: M) -> M {
+    match self.load() {
+      Some(saved) => (self.restore_fn)(saved, model),
+      None => model,
+    }
+  }
+
+  fn load(&self) -> Option<Value> {
+    let saved = self.storage.load(&self.key)?;
+
+    let mut saved = serde_json::from_str::<Value>(&saved).ok()?;
+
+    let mut version = saved["version"].as_u64()? as u32;
+    let mut model = saved["model"].take();
+
+    while version < self.version {
+      let Some(migrate) = self.migrations.get(&version) else {
+        tracing::warn!(version, "no migration for the persisted model");
+
+        return None;
+      };
+
+      model = migrate(model);
+      version += 1;
+    }
+
+    (version == self.version).then_some(model)
+  }
+
+  /// Schedules a save after [`Persistence::debounce`], replacing the
+  /// one which was already scheduled, unless the first unsaved change
+  /// waited for [`Persistence::max_wait`] by then.
+  pub(crate) fn schedule_save(&mut self) {
+    self.pending = Some(utils::sleep(self.debounce));
+
+    if self.deadline.is_none() {
+      self.deadline = Some(utils::sleep(self.max_wait));
+    }
+  }
+
+  pub(crate) fn poll_save(&mut self, cx: &mut task::Context) -> Poll<()> {
+    let Some(pending) = &mut self.pending else {
+      return Poll::Pending;
+    };
+
+    if let Some(deadline) = &mut self.deadline {
+      if deadline.poll_unpin(cx).is_ready() {
+        return Poll::Ready(());
+      }
+    }
+
+    pending.poll_unpin(cx)
+  }
+
+  /// Saves `model` right away, if a save was scheduled.
+  pub(crate) fn save_pending(&mut self, model: &M) {
+    self.deadline = None;
+
+    if self.pending.take().is_none() {
+      return;
+    }
+
+    match (self.save_fn)(model) {
+      Ok(saved) => {
+        let saved = serde_json::json!({
+          "version": self.version,
+          "model": saved,
+        });
+
+        self.storage.save(&self.key, &saved.to_string());
+      }
+      Err(err) => {
+        tracing::error!(%err, "failed to serialize the model");
+      }
+    }
+  }
+}
+
+/// Deserializes `saved` and applies it to `model` with `f`, keeping
+/// `model` as is if `saved` has the wrong shape.
+fn restore_or_keep<P: DeserializeOwned, M>(
+  saved: Value,
+  model: M,
+  f: impl FnOnce(P, M) -> M,
+) -> M {
+  match serde_json::from_value(saved) {
+    Ok(saved) => f(saved, model),
+    Err(err) => {
+      tracing::warn!(%err, "discarding the persisted model");
+
+      model
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    prelude::*,
+    testing::{
+      fixtures::{self, Counter, CounterMsg::Add},
+      TestApp,
+    },
+  };
+
+  #[test]
+  fn persisted_models_are_migrated_and_saved() {
+    use serde_json::json;
+
+    #[derive(DiffableModel, serde::Serialize, serde::Deserialize)]
+    struct Model {
+      count: i32,
+      label: String,
+    }
+
+    #[derive(Clone)]
+    enum Msg {
+      Increment,
+    }
+
+    let storage = MemoryStorage::default();
+
+    storage.save("counter", &json!({ "version": 1, "model": 5 }).to_string());
+
+    let mut app = TestApp::new(App::new_persisted(
+      Persistence::new(storage.clone(), "counter")
+        .version(2)
+        // Version 1 only stored the count
+        .migration(1, |count| json!({ "count": count, "label": "old" })),
+      || Model {
+        count: 0,
+        label: "new".to_string(),
+      },
+      |model: Model, Msg::Increment| Model {
+        count: model.count + 1,
+        ..model
+      },
+      |vm: &ModelViewModel, cx| {
+        // The view is built from the restored model
+        p(cx).attr("title", vm.label.get()).dyn_text(&vm.count)
+      },
+    ));
+
+    assert_eq!(app.model().count, 5);
+    assert_eq!(app.model().label, "old");
+    assert_eq!(
+      app.render_to_string(),
+      "<p title=\"old\"><!-- <DynChild> -->5<!-- </DynChild> --></p>"
+    );
+
+    app.dispatch(Msg::Increment);
+    app.run_until_idle();
+
+    let saved = storage.load("counter").unwrap();
+
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&saved).unwrap(),
+      json!({ "version": 2, "model": { "count": 6, "label": "old" } })
+    );
+    assert_eq!(
+      app.render_to_string(),
+      "<p title=\"old\"><!-- <DynChild> -->6<!-- </DynChild> --></p>"
+    );
+  }
+
+  #[cfg(feature = "time-travel")]
+  #[test]
+  fn time_travel_starts_from_the_restored_model() {
+    use serde_json::json;
+
+    let storage = MemoryStorage::default();
+
+    storage.save(
+      "counter",
+      &json!({ "version": 1, "model": { "count": 5 } }).to_string(),
+    );
+
+    let app = App::new_persisted(
+      Persistence::new(storage, "counter"),
+      || Counter { count: 0 },
+      fixtures::update,
+      fixtures::view,
+    )
+    .time_travel();
+
+    let history = app.time_travel_handle().unwrap();
+
+    let mut app = TestApp::new(app);
+
+    app.dispatch(Add(1));
+    app.run_until_idle();
+
+    history.jump_to(0);
+    app.run_until_idle();
+
+    assert_eq!(app.model().count, 5);
+  }
+}
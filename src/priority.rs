@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+/// How urgently a message should be processed.
+///
+/// Messages of higher priority are processed first, while messages of
+/// the same priority are processed in the order they were sent.
+#[derive(
+  Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum Priority {
+  /// Messages which should be handled right away, such as user input.
+  High,
+  /// Messages which are processed as they arrive.
+  #[default]
+  Normal,
+  /// Messages which can wait for the app to be idle, such as
+  /// analytics.
+  ///
+  /// On the web, these are processed during `requestIdleCallback`,
+  /// and natively, after yielding to the executor.
+  Idle,
+}
+
+pub(crate) type PriorityFn<Msg> = Box<dyn Fn(&Msg) -> Priority>;
+
+/// Messages waiting to be processed, by priority.
+pub(crate) struct MsgQueue<Msg> {
+  queues: [VecDeque<Msg>; 3],
+  priority_fn: Option<PriorityFn<Msg>>,
+}
+
+impl<Msg> Default for MsgQueue<Msg> {
+  fn default() -> Self {
+    Self {
+      queues: Default::default(),
+      priority_fn: None,
+    }
+  }
+}
+
+impl<Msg> MsgQueue<Msg> {
+  pub fn set_priority_fn(&mut self, f: PriorityFn<Msg>) {
+    self.priority_fn = Some(f);
+  }
+
+  pub fn push(&mut self, msg: Msg) {
+    let priority = self
+      .priority_fn
+      .as_ref()
+      .map_or(Priority::Normal, |f| f(&msg));
+
+    self.queues[priority as usize].push_back(msg);
+  }
+
+  /// Takes the most urgent message which does not have to wait for
+  /// the app to be idle.
+  pub fn pop(&mut self) -> Option<Msg> {
+    self.queues[..Priority::Idle as usize]
+      .iter_mut()
+      .find_map(VecDeque::pop_front)
+  }
+
+  /// The message which [`MsgQueue::pop`] would take.
+  pub fn peek(&self) -> Option<&Msg> {
+    self.queues[..Priority::Idle as usize]
+      .iter()
+      .find_map(VecDeque::front)
+  }
+
+  /// Takes the oldest message which was waiting for the app to be
+  /// idle.
+  pub fn pop_idle(&mut self) -> Option<Msg> {
+    self.queues[Priority::Idle as usize].pop_front()
+  }
+
+  pub fn has_non_idle(&self) -> bool {
+    self.queues[..Priority::Idle as usize]
+      .iter()
+      .any(|queue| !queue.is_empty())
+  }
+
+  pub fn has_idle(&self) -> bool {
+    !self.queues[Priority::Idle as usize].is_empty()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+
+  #[test]
+  fn higher_priority_messages_are_processed_first() {
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      KeyPress,
+      Synced(u8),
+      Track,
+    }
+
+    let mut app = TestApp::new(
+      App::new(|| (), |_, _| (), |_, cx: Ctx<Msg>| div(cx)).priority(|msg| {
+        match msg {
+          Msg::KeyPress => Priority::High,
+          Msg::Synced(_) => Priority::Normal,
+          Msg::Track => Priority::Idle,
+        }
+      }),
+    );
+
+    for msg in [Msg::Synced(0), Msg::Track, Msg::Synced(1), Msg::KeyPress] {
+      app.dispatch(msg);
+    }
+
+    app.run_until_idle();
+
+    assert_eq!(
+      app.messages(),
+      [Msg::KeyPress, Msg::Synced(0), Msg::Synced(1), Msg::Track]
+    );
+  }
+}
@@ -0,0 +1,403 @@
+//! Recording the messages an app processes, so a session can be
+//! exported, such as for a bug report, and replayed deterministically
+//! in a native test.
+
+use error_stack::{report, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{cell::RefCell, rc::Rc};
+
+/// The version of the session format written by this crate.
+const SESSION_VERSION: u32 = 1;
+
+/// An error returned when a [`Session`] could not be exported or
+/// imported.
+#[derive(Clone, Copy, Debug, derive_more::Display)]
+pub enum SessionError {
+  #[display(fmt = "failed to serialize the session")]
+  Serialize,
+  #[display(fmt = "failed to deserialize the session")]
+  Deserialize,
+  #[display(fmt = "the session was recorded with an unsupported version")]
+  UnsupportedVersion,
+  #[display(fmt = "failed to access the session file")]
+  Io,
+}
+
+impl Context for SessionError {}
+
+/// How a [`Session`] is encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionFormat {
+  /// Human-readable, for attaching to bug reports.
+  Json,
+  /// Compact, for long sessions.
+  Bincode,
+}
+
+/// The model an app started running with, followed by every message
+/// it received, in order.
+///
+/// Messages whose async update was cancelled are left out, as they
+/// never changed the model.
+///
+/// Sessions are recorded with `App::record`, and replayed with
+/// [`TestApp::replay`](crate::testing::TestApp::replay).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session<M, Msg> {
+  version: u32,
+  init_model: M,
+  msgs: Vec<Msg>,
+}
+
+impl<M, Msg> Session<M, Msg> {
+  pub(crate) fn new(init_model: M) -> Self {
+    Self {
+      version: SESSION_VERSION,
+      init_model,
+      msgs: vec![],
+    }
+  }
+
+  /// The model the app started with.
+  pub fn init_model(&self) -> &M {
+    &self.init_model
+  }
+
+  /// The recorded messages, in the order they were received.
+  pub fn messages(&self) -> &[Msg] {
+    &self.msgs
+  }
+
+  pub(crate) fn into_parts(self) -> (M, Vec<Msg>) {
+    (self.init_model, self.msgs)
+  }
+
+  /// Encodes the session with `format`.
+  pub fn to_bytes(&self, format: SessionFormat) -> Result<Vec<u8>, SessionError>
+  where
+    M: Serialize,
+    Msg: Serialize,
+  {
+    let bytes = match format {
+      SessionFormat::Json => {
+        serde_json::to_vec(self).map_err(|err| err.to_string())
+      }
+      SessionFormat::Bincode => {
+        bincode::serialize(self).map_err(|err| err.to_string())
+      }
+    };
+
+    bytes.map_err(|err| report!(SessionError::Serialize).attach_printable(err))
+  }
+
+  /// Decodes a session which was encoded with `format`.
+  pub fn from_bytes(
+    bytes: &[u8],
+    format: SessionFormat,
+  ) -> Result<Self, SessionError>
+  where
+    M: DeserializeOwned,
+    Msg: DeserializeOwned,
+  {
+    let session = match format {
+      SessionFormat::Json => {
+        serde_json::from_slice::<Self>(bytes).map_err(|err| err.to_string())
+      }
+      SessionFormat::Bincode => {
+        bincode::deserialize::<Self>(bytes).map_err(|err| err.to_string())
+      }
+    }
+    .map_err(|err| report!(SessionError::Deserialize).attach_printable(err))?;
+
+    if session.version != SESSION_VERSION {
+      return Err(
+        report!(SessionError::UnsupportedVersion)
+          .attach_printable(format!("version {}", session.version)),
+      );
+    }
+
+    Ok(session)
+  }
+
+  /// Writes the session to the file at `path`.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn write_to(
+    &self,
+    path: impl AsRef<std::path::Path>,
+    format: SessionFormat,
+  ) -> Result<(), SessionError>
+  where
+    M: Serialize,
+    Msg: Serialize,
+  {
+    let bytes = self.to_bytes(format)?;
+
+    std::fs::write(path, bytes).map_err(|err| {
+      report!(SessionError::Io).attach_printable(err.to_string())
+    })
+  }
+
+  /// Reads a session from the file at `path`.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn read_from(
+    path: impl AsRef<std::path::Path>,
+    format: SessionFormat,
+  ) -> Result<Self, SessionError>
+  where
+    M: DeserializeOwned,
+    Msg: DeserializeOwned,
+  {
+    let bytes = std::fs::read(path).map_err(|err| {
+      report!(SessionError::Io).attach_printable(err.to_string())
+    })?;
+
+    Self::from_bytes(&bytes, format)
+  }
+}
+
+/// The session being recorded by the runtime, once it started
+/// running.
+pub(crate) struct Recorder<M, Msg> {
+  session: Rc<RefCell<Option<Session<M, Msg>>>>,
+  clone_model: fn(&M) -> M,
+  clone_msg: fn(&Msg) -> Msg,
+}
+
+impl<M, Msg> Recorder<M, Msg> {
+  pub fn new() -> Self
+  where
+    M: Clone,
+    Msg: Clone,
+  {
+    Self {
+      session: Default::default(),
+      clone_model: M::clone,
+      clone_msg: Msg::clone,
+    }
+  }
+
+  /// Starts the session from `model`, unless it already started.
+  pub fn start(&self, model: &M) {
+    let mut session = self.session.borrow_mut();
+
+    if session.is_none() {
+      *session = Some(Session::new((self.clone_model)(model)));
+    }
+  }
+
+  pub fn handle(&self) -> RecordingHandle<M, Msg> {
+    RecordingHandle {
+      session: self.session.clone(),
+    }
+  }
+
+  pub fn record(&self, msg: &Msg) {
+    if let Some(session) = &mut *self.session.borrow_mut() {
+      session.msgs.push((self.clone_msg)(msg));
+    }
+  }
+
+  /// Forgets the last recorded message, whose update was cancelled.
+  pub fn forget_last(&self) {
+    if let Some(session) = &mut *self.session.borrow_mut() {
+      session.msgs.pop();
+    }
+  }
+}
+
+/// A handle for exporting the [`Session`] an app is recording.
+///
+/// Clones share the same session.
+#[derive(educe::Educe)]
+#[educe(Clone)]
+pub struct RecordingHandle<M, Msg> {
+  session: Rc<RefCell<Option<Session<M, Msg>>>>,
+}
+
+impl<M: Clone, Msg: Clone> RecordingHandle<M, Msg> {
+  /// A copy of the session recorded so far, or `None` if the app did
+  /// not start running yet.
+  pub fn session(&self) -> Option<Session<M, Msg>> {
+    self.session.borrow().clone()
+  }
+}
+
+impl<M, Msg> RecordingHandle<M, Msg> {
+  /// The number of messages recorded so far.
+  pub fn len(&self) -> usize {
+    self
+      .session
+      .borrow()
+      .as_ref()
+      .map_or(0, |session| session.msgs.len())
+  }
+
+  /// Returns `true` if no messages were recorded yet.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+
+  #[test]
+  fn recorded_sessions_replay_deterministically() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, DiffableModel, Serialize, Deserialize)]
+    struct Model {
+      count: i32,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    enum Msg {
+      Add(i32),
+      Double,
+    }
+
+    // Adding doubles the count afterwards through a command, which
+    // must not run again when replaying
+    fn update(model: Model, msg: Msg) -> (Model, Cmd<Msg>) {
+      match msg {
+        Msg::Add(n) => (
+          Model {
+            count: model.count + n,
+          },
+          Cmd::msg(Msg::Double),
+        ),
+        Msg::Double => (
+          Model {
+            count: model.count * 2,
+          },
+          Cmd::none(),
+        ),
+      }
+    }
+
+    type UpdateFn = fn(Model, Msg) -> (Model, Cmd<Msg>);
+
+    fn app(init: Model) -> App<Model, Msg, UpdateFn> {
+      App::new(
+        move || init,
+        update as UpdateFn,
+        |vm: &ModelViewModel, cx| p(cx).dyn_text(&vm.count),
+      )
+    }
+
+    let recorded = app(Model { count: 1 }).record();
+
+    let recording = recorded.recording_handle().unwrap();
+
+    let mut live = TestApp::new(recorded);
+
+    live.dispatch(Msg::Add(2));
+    live.run_until_idle();
+    live.dispatch(Msg::Add(1));
+    live.run_until_idle();
+
+    assert_eq!(live.model().count, 14);
+
+    let session = recording.session().unwrap();
+
+    assert_eq!(
+      session.messages(),
+      [Msg::Add(2), Msg::Double, Msg::Add(1), Msg::Double]
+    );
+
+    for format in [SessionFormat::Json, SessionFormat::Bincode] {
+      let path = std::env::temp_dir().join(format!(
+        "frappe-tea-session-{format:?}-{}",
+        std::process::id()
+      ));
+
+      session.write_to(&path, format).unwrap();
+
+      let loaded = Session::read_from(&path, format).unwrap();
+
+      std::fs::remove_file(&path).unwrap();
+
+      let replayed = TestApp::replay(app, loaded);
+
+      assert_eq!(replayed.model().count, 14);
+      assert_eq!(
+        replayed.render_to_string(),
+        "<p><!-- <DynChild> -->14<!-- </DynChild> --></p>"
+      );
+    }
+  }
+
+  #[test]
+  fn recorded_sessions_leave_out_cancelled_updates() {
+    use futures::future;
+    use std::{cell::Cell, rc::Rc, task::Poll};
+
+    #[derive(Clone, DiffableModel)]
+    struct Model {
+      results: String,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Search(&'static str),
+    }
+
+    // Updates stay pending while held
+    let held = Rc::new(Cell::new(true));
+
+    let app = clone!([held], move |init: Model| {
+      App::new_async(
+        move || init,
+        clone!([held], move |model: Model, Msg::Search(query)| {
+          let held = held.clone();
+
+          async move {
+            future::poll_fn(|_| {
+              if held.get() {
+                Poll::Pending
+              } else {
+                Poll::Ready(())
+              }
+            })
+            .await;
+
+            Model {
+              results: format!("{}{query}", model.results),
+            }
+          }
+        }),
+        |vm: &ModelViewModel, cx| p(cx).dyn_text(&vm.results),
+      )
+      .pending_policy(|_, _| PendingPolicy::Cancel)
+    });
+
+    let recorded = app(Model {
+      results: String::new(),
+    })
+    .record();
+
+    let recording = recorded.recording_handle().unwrap();
+
+    assert!(recording.session().is_none());
+
+    let mut live = TestApp::new(recorded);
+
+    live.dispatch(Msg::Search("a"));
+    live.dispatch(Msg::Search("b"));
+    live.run_until_idle();
+
+    held.set(false);
+    live.run_until_idle();
+
+    assert_eq!(live.model().results, "b");
+
+    let session = recording.session().unwrap();
+
+    assert_eq!(session.messages(), [Msg::Search("b")]);
+
+    let replayed = TestApp::replay(app, session);
+
+    assert_eq!(replayed.model().results, "b");
+  }
+}
@@ -0,0 +1,359 @@
+//! Loading async data, such as from an API, into the view.
+
+use crate::{
+  observable::{IntoStream, Observable},
+  runtime::Ctx,
+  utils::{Spawner, TaskGuard},
+};
+use futures::{
+  channel::mpsc::UnboundedReceiver, future::LocalBoxFuture, FutureExt,
+  Stream, StreamExt,
+};
+use std::{
+  cell::{Cell, RefCell},
+  future::Future,
+  pin::Pin,
+  rc::{Rc, Weak},
+  task::{self, Poll},
+};
+
+/// The state of a [`Resource`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResourceState<T, E> {
+  /// The data is being fetched, or is waiting for a source value to
+  /// be fetched with.
+  Loading,
+  Ready(T),
+  Failed(E),
+}
+
+impl<T, E> ResourceState<T, E> {
+  pub fn is_loading(&self) -> bool {
+    matches!(self, Self::Loading)
+  }
+}
+
+/// Counts the resources of an app which are still loading, so
+/// server-side rendering can wait for them.
+#[derive(Default)]
+pub(crate) struct Tracker {
+  loading: Cell<usize>,
+}
+
+impl Tracker {
+  fn start(self: Rc<Self>) -> Loading {
+    self.loading.set(self.loading.get() + 1);
+
+    Loading(self)
+  }
+
+  /// Returns `true` while any resource is loading.
+  #[cfg(feature = "ssr")]
+  pub fn is_loading(&self) -> bool {
+    self.loading.get() > 0
+  }
+}
+
+/// Marks a resource as loading until dropped.
+struct Loading(Rc<Tracker>);
+
+impl Drop for Loading {
+  fn drop(&mut self) {
+    self.0.loading.set(self.0.loading.get() - 1);
+  }
+}
+
+type FetchFn<T, E> =
+  Box<dyn FnMut() -> LocalBoxFuture<'static, Result<T, E>>>;
+
+/// Async data which is fetched in the background, created with
+/// [`Ctx::resource`] or [`Ctx::resource_with`].
+///
+/// Its [`ResourceState`] can be rendered by passing the resource to
+/// `dyn_child`, which keeps it alive for as long as the view is
+/// shown.
+///
+/// ```no_run
+/// # use frappe_tea::prelude::*;
+/// # #[derive(Clone)]
+/// # struct User {
+/// #   name: String,
+/// # }
+/// # async fn fetch_user() -> Result<User, String> {
+/// #   unimplemented!()
+/// # }
+/// # fn profile(cx: Ctx<()>) -> impl IntoView<()> {
+/// let user = cx.resource(|| fetch_user());
+///
+/// div(cx).dyn_child(&user, |cx, state| match state {
+///   ResourceState::Loading => text(cx, "Loading..."),
+///   ResourceState::Ready(user) => text(cx, user.name),
+///   ResourceState::Failed(err) => text(cx, err),
+/// })
+/// # }
+/// ```
+///
+/// Clones share the same data.
+#[derive(educe::Educe)]
+#[educe(Clone)]
+pub struct Resource<T, E>(Rc<ResourceInner<T, E>>);
+
+struct ResourceInner<T, E> {
+  state: Observable<ResourceState<T, E>>,
+  /// Fetches with the latest source value, once there is one.
+  fetch_fn: RefCell<Option<FetchFn<T, E>>>,
+  fetch: RefCell<Option<TaskGuard>>,
+  source: RefCell<Option<TaskGuard>>,
+  /// Set while waiting for the first source value.
+  waiting: RefCell<Option<Loading>>,
+  tracker: Option<Rc<Tracker>>,
+  spawner: Spawner,
+}
+
+impl<T: Clone + 'static, E: Clone + 'static> Resource<T, E> {
+  fn new(tracker: Option<Rc<Tracker>>, spawner: Spawner) -> Self {
+    Self(Rc::new(ResourceInner {
+      state: Observable::new(ResourceState::Loading),
+      fetch_fn: Default::default(),
+      fetch: Default::default(),
+      source: Default::default(),
+      waiting: Default::default(),
+      tracker,
+      spawner,
+    }))
+  }
+
+  /// A copy of the current state.
+  pub fn state(&self) -> ResourceState<T, E> {
+    self.0.state.get()
+  }
+
+  /// Fetches the data again, cancelling the fetch in progress.
+  ///
+  /// Does nothing if the resource is still waiting for its first
+  /// source value.
+  pub fn refetch(&self) {
+    let Some(fut) = self.0.fetch_fn.borrow_mut().as_mut().map(|f| f()) else {
+      return;
+    };
+
+    self.cancel();
+
+    self.0.state.set(ResourceState::Loading);
+
+    let loading = self.0.tracker.clone().map(Tracker::start);
+
+    let state = self.0.state.clone();
+
+    let fetch = self.0.spawner.spawn_guarded(async move {
+      let state_value = match fut.await {
+        Ok(data) => ResourceState::Ready(data),
+        Err(err) => ResourceState::Failed(err),
+      };
+
+      state.set(state_value);
+
+      drop(loading);
+    });
+
+    *self.0.fetch.borrow_mut() = Some(fetch);
+  }
+
+  /// Cancels the fetch in progress, if any, leaving the resource
+  /// [`Loading`](ResourceState::Loading) until it is refetched.
+  pub fn cancel(&self) {
+    self.0.fetch.borrow_mut().take();
+  }
+
+  /// Returns a [`Stream`] which yields the current state, followed
+  /// by every state the resource goes through.
+  ///
+  /// The stream keeps the resource alive.
+  pub fn stream(&self) -> ResourceStream<T, E> {
+    ResourceStream {
+      states: self.0.state.stream(),
+      _resource: self.clone(),
+    }
+  }
+}
+
+/// The [`Stream`] returned by [`Resource::stream`].
+pub struct ResourceStream<T, E> {
+  states: UnboundedReceiver<ResourceState<T, E>>,
+  _resource: Resource<T, E>,
+}
+
+impl<T, E> Stream for ResourceStream<T, E> {
+  type Item = ResourceState<T, E>;
+
+  fn poll_next(
+    mut self: Pin<&mut Self>,
+    cx: &mut task::Context,
+  ) -> Poll<Option<Self::Item>> {
+    self.states.poll_next_unpin(cx)
+  }
+}
+
+impl<T: Clone + 'static, E: Clone + 'static> IntoStream for Resource<T, E> {
+  type Item = ResourceState<T, E>;
+  type Stream = ResourceStream<T, E>;
+
+  fn into_stream(self) -> Self::Stream {
+    self.stream()
+  }
+}
+
+impl<T: Clone + 'static, E: Clone + 'static> IntoStream for &Resource<T, E> {
+  type Item = ResourceState<T, E>;
+  type Stream = ResourceStream<T, E>;
+
+  fn into_stream(self) -> Self::Stream {
+    self.stream()
+  }
+}
+
+impl<Msg: 'static> Ctx<Msg> {
+  /// Creates a [`Resource`] which starts fetching its data with
+  /// `fetch` right away.
+  pub fn resource<T, E, F, Fut>(&self, mut fetch: F) -> Resource<T, E>
+  where
+    T: Clone + 'static,
+    E: Clone + 'static,
+    F: FnMut() -> Fut + 'static,
+    Fut: Future<Output = Result<T, E>> + 'static,
+  {
+    let resource =
+      Resource::new(self.get_context::<Tracker>(), self.spawner.clone());
+
+    *resource.0.fetch_fn.borrow_mut() =
+      Some(Box::new(move || fetch().boxed_local()));
+
+    resource.refetch();
+
+    resource
+  }
+
+  /// Creates a [`Resource`] which fetches its data with `fetch` each
+  /// time `source` yields a value, such as the id of the item to
+  /// show.
+  ///
+  /// Refetching uses the latest source value.
+  pub fn resource_with<S, T, E, F, Fut>(
+    &self,
+    source: S,
+    fetch: F,
+  ) -> Resource<T, E>
+  where
+    S: IntoStream,
+    S::Item: Clone + 'static,
+    T: Clone + 'static,
+    E: Clone + 'static,
+    F: FnMut(S::Item) -> Fut + 'static,
+    Fut: Future<Output = Result<T, E>> + 'static,
+  {
+    let tracker = self.get_context::<Tracker>();
+
+    let resource = Resource::new(tracker.clone(), self.spawner.clone());
+
+    *resource.0.waiting.borrow_mut() = tracker.map(Tracker::start);
+
+    let fetch = Rc::new(RefCell::new(fetch));
+
+    // The source only holds a weak reference, so dropping the
+    // resource also stops watching it
+    let weak = Rc::downgrade(&resource.0);
+
+    let watch = source.into_stream().for_each(move |value| {
+      if let Some(inner) = Weak::upgrade(&weak) {
+        let fetch = fetch.clone();
+
+        *inner.fetch_fn.borrow_mut() = Some(Box::new(move || {
+          (fetch.borrow_mut())(value.clone()).boxed_local()
+        }));
+
+        let resource = Resource(inner);
+
+        resource.refetch();
+
+        resource.0.waiting.borrow_mut().take();
+      }
+
+      async {}
+    });
+
+    let weak = Rc::downgrade(&resource.0);
+
+    // A source which ends without a value leaves nothing to wait for
+    let watch = watch.map(move |()| {
+      if let Some(inner) = Weak::upgrade(&weak) {
+        inner.waiting.borrow_mut().take();
+      }
+    });
+
+    *resource.0.source.borrow_mut() =
+      Some(self.spawner.spawn_guarded(watch));
+
+    resource
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::prelude::*;
+
+  #[cfg(feature = "ssr")]
+  #[test]
+  fn resources_load_before_rendering() {
+    use futures::future;
+    use std::{cell::RefCell, rc::Rc};
+
+    futures::executor::block_on(async {
+      let user_id = Observable::new(1);
+      let stalled = Rc::new(RefCell::new(None));
+
+      let app = App::new(
+        || (),
+        |_, ()| (),
+        clone!([user_id, stalled], move |_, cx: Ctx<()>| {
+          let user = cx.resource_with(&user_id, |id| async move {
+            match id {
+              0 => Err("no such user"),
+              id => Ok(format!("user {id}")),
+            }
+          });
+
+          *stalled.borrow_mut() =
+            Some(cx.resource(future::pending::<Result<(), ()>>));
+
+          p(cx).dyn_child(&user, |cx, state| match state {
+            ResourceState::Loading => text(cx, "loading"),
+            ResourceState::Ready(name) => text(cx, name),
+            ResourceState::Failed(err) => text(cx, err),
+          })
+        }),
+      );
+
+      let stalled = stalled.borrow_mut().take().unwrap();
+
+      // Otherwise, rendering would wait for it forever
+      stalled.cancel();
+
+      app.resources_ready().await;
+
+      assert_eq!(
+        app.render_to_string(),
+        "<p><!-- <DynChild> -->user 1<!-- </DynChild> --></p>"
+      );
+      assert_eq!(stalled.state(), ResourceState::Loading);
+
+      user_id.set(0);
+
+      app.resources_ready().await;
+
+      assert_eq!(
+        app.render_to_string(),
+        "<p><!-- <DynChild> -->no such user<!-- </DynChild> --></p>"
+      );
+    });
+  }
+}
@@ -1,13 +1,34 @@
+#[cfg(feature = "hot-reload")]
+use crate::hot_reload::Reloader;
+#[cfg(feature = "persistence")]
+use crate::persistence::Persistence;
+#[cfg(feature = "recording")]
+use crate::recording::{self, RecordingHandle};
+#[cfg(feature = "time-travel")]
+use crate::time_travel::{self, TimeTravelHandle};
+use crate::{
+  cmd::Cmd,
+  middleware::{Middleware, Pipeline},
+  observable::Observable,
+  priority::{MsgQueue, PriorityFn},
+  sub::{Sub, SubId},
+  utils,
+};
+use error_stack::{Context, Report};
 use futures::{
-  channel::mpsc::{UnboundedReceiver, UnboundedSender},
+  channel::mpsc::{Receiver, UnboundedReceiver, UnboundedSender},
+  future::{self, AbortHandle, LocalBoxFuture},
   stream::StreamExt,
+  FutureExt, Stream,
 };
 use std::{
   any::{self, Any, TypeId},
   cell::RefCell,
   collections::HashMap,
-  pin::Pin,
+  fmt,
   rc::Rc,
+  task::{self, Poll},
+  time::Duration,
 };
 
 pub trait DiffableModel {
@@ -26,6 +47,42 @@ impl DiffableModel for () {
   fn diff(&self, _: &mut Self::ViewModel) {}
 }
 
+/// The view model of a [`Vec`] of [`DiffableModel`]s.
+pub struct VecViewModel<VM> {
+  /// The view model of each item.
+  pub items: Vec<VM>,
+  /// The number of items, which is set whenever items are added
+  /// or removed.
+  pub len: Observable<usize>,
+}
+
+impl<T: DiffableModel> DiffableModel for Vec<T> {
+  type ViewModel = VecViewModel<T::ViewModel>;
+
+  fn to_view_model(&self) -> Self::ViewModel {
+    VecViewModel {
+      items: self.iter().map(DiffableModel::to_view_model).collect(),
+      len: Observable::new(self.len()),
+    }
+  }
+
+  fn diff(&self, view_model: &mut Self::ViewModel) {
+    view_model.items.truncate(self.len());
+
+    for (item, item_view_model) in self.iter().zip(&mut view_model.items) {
+      item.diff(item_view_model);
+    }
+
+    let new_items = &self[view_model.items.len()..];
+
+    view_model
+      .items
+      .extend(new_items.iter().map(DiffableModel::to_view_model));
+
+    view_model.len.set_if_changed(&self.len());
+  }
+}
+
 pub trait IntoMsg<Msg>: Sized {
   fn into_msg(self) -> Option<Msg>;
 }
@@ -42,21 +99,345 @@ impl<Msg> IntoMsg<Msg> for () {
   }
 }
 
+type UpdateResult<M, Msg> = Result<(M, Cmd<Msg>), Box<dyn AnyReport>>;
+
+/// The result of an update function, once converted with
+/// [`IntoUpdate`].
+pub enum Update<M, Msg> {
+  /// The update finished right away.
+  Ready(UpdateResult<M, Msg>),
+  /// The update finishes once the future resolves.
+  Pending(LocalBoxFuture<'static, UpdateResult<M, Msg>>),
+}
+
+/// The return value of an update function.
+///
+/// This allows update functions to return either just the new model,
+/// or the new model along with a [`Cmd`] to be executed, as well as
+/// a [`Result`] of either, for updates which can fail, or an
+/// [`AsyncUpdate`] resolving to any of these.
+pub trait IntoUpdate<M, Msg> {
+  /// Copies the model and message before they are consumed by an
+  /// update which can fail, so they are still around if it does.
+  ///
+  /// Returning [`None`] is only allowed for updates which always
+  /// finish right away with [`Ok`], as the runtime has nothing to
+  /// fall back to otherwise.
+  fn backup(model: &M, msg: &Msg) -> Option<(M, Msg)>;
+
+  fn into_update(self) -> Update<M, Msg>;
+}
+
+impl<M, Msg> IntoUpdate<M, Msg> for M {
+  fn backup(_: &M, _: &Msg) -> Option<(M, Msg)> {
+    None
+  }
+
+  fn into_update(self) -> Update<M, Msg> {
+    Update::Ready(Ok((self, Cmd::none())))
+  }
+}
+
+impl<M, Msg> IntoUpdate<M, Msg> for (M, Cmd<Msg>) {
+  fn backup(_: &M, _: &Msg) -> Option<(M, Msg)> {
+    None
+  }
+
+  fn into_update(self) -> Update<M, Msg> {
+    Update::Ready(Ok(self))
+  }
+}
+
+/// When the update fails, the previous model is kept, and the error is
+/// passed to the error handler set with `App::on_update_error`.
+impl<M, Msg, E> IntoUpdate<M, Msg> for Result<M, Report<E>>
+where
+  M: Clone,
+  Msg: Clone,
+  E: Context,
+{
+  fn backup(model: &M, msg: &Msg) -> Option<(M, Msg)> {
+    Some((model.clone(), msg.clone()))
+  }
+
+  fn into_update(self) -> Update<M, Msg> {
+    Update::Ready(
+      self
+        .map(|model| (model, Cmd::none()))
+        .map_err(|report| Box::new(report) as _),
+    )
+  }
+}
+
+impl<M, Msg, E> IntoUpdate<M, Msg> for Result<(M, Cmd<Msg>), Report<E>>
+where
+  M: Clone,
+  Msg: Clone,
+  E: Context,
+{
+  fn backup(model: &M, msg: &Msg) -> Option<(M, Msg)> {
+    Some((model.clone(), msg.clone()))
+  }
+
+  fn into_update(self) -> Update<M, Msg> {
+    Update::Ready(self.map_err(|report| Box::new(report) as _))
+  }
+}
+
+/// An update which finishes once a [`Future`] resolves, such as when
+/// reading from IndexedDB.
+///
+/// The model keeps its previous value until then, and the view model
+/// is only diffed afterwards. Messages which arrive in the meantime
+/// wait for the update to finish, unless an app's `pending_policy`
+/// says otherwise.
+///
+/// [`Future`]: std::future::Future
+pub struct AsyncUpdate<M, Msg>(LocalBoxFuture<'static, UpdateResult<M, Msg>>);
+
+impl<M: 'static, Msg: 'static> AsyncUpdate<M, Msg> {
+  pub fn new<U: IntoUpdate<M, Msg>>(
+    fut: impl std::future::Future<Output = U> + 'static,
+  ) -> Self {
+    Self(
+      async move {
+        match fut.await.into_update() {
+          Update::Ready(update) => update,
+          Update::Pending(fut) => fut.await,
+        }
+      }
+      .boxed_local(),
+    )
+  }
+}
+
+/// The update function of apps created with `App::new_async`.
+pub type AsyncUpdateFn<M, Msg> = Box<dyn FnMut(M, Msg) -> AsyncUpdate<M, Msg>>;
+
+/// The model is backed up so it can be restored if the update fails or
+/// is cancelled.
+impl<M, Msg> IntoUpdate<M, Msg> for AsyncUpdate<M, Msg>
+where
+  M: Clone,
+  Msg: Clone,
+{
+  fn backup(model: &M, msg: &Msg) -> Option<(M, Msg)> {
+    Some((model.clone(), msg.clone()))
+  }
+
+  fn into_update(self) -> Update<M, Msg> {
+    Update::Pending(self.0)
+  }
+}
+
+/// A [`Report`] with its context type erased.
+pub trait AnyReport: fmt::Debug + fmt::Display {
+  fn as_any(&self) -> &dyn Any;
+}
+
+impl<C: Context> AnyReport for Report<C> {
+  fn as_any(&self) -> &dyn Any {
+    self
+  }
+}
+
+/// An error returned from an update function, along with the message
+/// which caused it.
+pub struct UpdateError<Msg> {
+  msg: Msg,
+  report: Box<dyn AnyReport>,
+}
+
+impl<Msg> UpdateError<Msg> {
+  /// The message which caused the error.
+  pub fn msg(&self) -> &Msg {
+    &self.msg
+  }
+
+  /// The returned report, if its context is `C`.
+  pub fn report<C: Context>(&self) -> Option<&Report<C>> {
+    self.report.as_any().downcast_ref()
+  }
+
+  /// Takes the message which caused the error, such as for retrying
+  /// it.
+  pub fn into_failed_msg(self) -> Msg {
+    self.msg
+  }
+}
+
+impl<Msg> fmt::Debug for UpdateError<Msg> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(&self.report, f)
+  }
+}
+
+impl<Msg> fmt::Display for UpdateError<Msg> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Display::fmt(&self.report, f)
+  }
+}
+
+type DispatchFn<Msg> = Rc<dyn Fn(Msg)>;
+
+/// Sends messages from the view to the runtime, possibly through
+/// any number of message mappings.
+///
+/// Clones share the same underlying slot, so that views which were
+/// built before knowing where their messages should go, such as the
+/// ones returned from [`View::map_msg`], can be bound once they
+/// are attached to a parent. Slots are bound at most once, so each
+/// mapping wraps the dispatcher it was given instead of redirecting
+/// the messages of other clones.
+///
+/// [`View::map_msg`]: crate::prelude::View::map_msg
+#[derive(educe::Educe)]
+#[educe(Clone)]
+pub(crate) struct MsgDispatcher<Msg>(Rc<RefCell<Option<DispatchFn<Msg>>>>);
+
+impl<Msg: 'static> MsgDispatcher<Msg> {
+  pub fn new(f: impl Fn(Msg) + 'static) -> Self {
+    Self(Rc::new(RefCell::new(Some(Rc::new(f)))))
+  }
+
+  /// Creates a dispatcher which drops all messages until it is bound.
+  pub fn unbound() -> Self {
+    Self(Default::default())
+  }
+
+  pub fn dispatch(&self, msg: Msg) {
+    let f = self.0.borrow().clone();
+
+    if let Some(f) = f {
+      f(msg);
+    } else {
+      debug!("message dispatched from a view which is not attached");
+    }
+  }
+
+  /// Creates a dispatcher which sends all messages through `f` to
+  /// `target`.
+  pub fn mapped<Source: 'static>(
+    &self,
+    f: impl Fn(Source) -> Msg + 'static,
+  ) -> MsgDispatcher<Source> {
+    let target = self.clone();
+
+    MsgDispatcher::new(move |msg| target.dispatch(f(msg)))
+  }
+
+  /// Sends all messages through `f` to `target`, if this dispatcher is
+  /// not yet bound.
+  pub fn bind_with<Target: 'static>(
+    &self,
+    target: &MsgDispatcher<Target>,
+    f: impl Fn(Msg) -> Target + 'static,
+  ) {
+    let mut slot = self.0.borrow_mut();
+
+    if slot.is_none() {
+      let target = target.clone();
+
+      *slot = Some(Rc::new(move |msg| target.dispatch(f(msg))));
+    }
+  }
+
+  /// Binds this dispatcher to `parent`, if it is not yet bound.
+  pub fn bind_to(&self, parent: &Self) {
+    if !Rc::ptr_eq(&self.0, &parent.0) {
+      self.bind_with(parent, |msg| msg);
+    }
+  }
+}
+
 #[derive(educe::Educe)]
 #[educe(Clone)]
 pub struct Ctx<Msg> {
-  pub(crate) msg_dispatcher: UnboundedSender<Msg>,
-  pub(crate) data: Rc<RefCell<HashMap<any::TypeId, ContextData>>>,
+  pub(crate) msg_dispatcher: MsgDispatcher<Msg>,
+  pub(crate) data: Rc<ContextScope>,
+  /// Spawns the tasks of the view onto the executor of its app.
+  pub(crate) spawner: utils::Spawner,
 }
 
-impl<Msg> Ctx<Msg> {
-  pub(crate) fn new(msg_dispatcher: UnboundedSender<Msg>) -> Self {
+impl<Msg: 'static> Ctx<Msg> {
+  pub(crate) fn new(
+    msg_sender: UnboundedSender<Msg>,
+    spawner: utils::Spawner,
+  ) -> Self {
     Self {
-      msg_dispatcher,
+      msg_dispatcher: MsgDispatcher::new(move |msg| {
+        let _ = msg_sender.unbounded_send(msg);
+      }),
       data: Default::default(),
+      spawner,
+    }
+  }
+
+  /// Sends `msg` to the update function, as event handlers do.
+  pub fn dispatch(&self, msg: Msg) {
+    self.msg_dispatcher.dispatch(msg);
+  }
+
+  /// Creates a [`Ctx`] which sends messages to another runtime, while
+  /// sharing contexts with this one.
+  pub(crate) fn with_msg_sender<OtherMsg: 'static>(
+    &self,
+    msg_sender: UnboundedSender<OtherMsg>,
+  ) -> Ctx<OtherMsg> {
+    Ctx {
+      data: self.data.clone(),
+      ..Ctx::new(msg_sender, self.spawner.clone())
+    }
+  }
+
+  /// Creates a [`Ctx`] for a nested module with its own message type,
+  /// whose messages are passed through `f` before reaching this one.
+  ///
+  /// Contexts set on either [`Ctx`] are shared.
+  pub fn map<ChildMsg: 'static>(
+    &self,
+    f: impl Fn(ChildMsg) -> Msg + 'static,
+  ) -> Ctx<ChildMsg> {
+    Ctx {
+      msg_dispatcher: self.msg_dispatcher.mapped(f),
+      data: self.data.clone(),
+      spawner: self.spawner.clone(),
+    }
+  }
+}
+
+impl<Msg> Ctx<Msg> {
+  /// Creates a [`Ctx`] for a subtree of the view, in which `data` is
+  /// set as a context, shadowing any context of the same type set on
+  /// this one.
+  ///
+  /// Contexts which are not found in the subtree are looked up on
+  /// this [`Ctx`], but the ones set in the subtree are not visible
+  /// from here.
+  #[track_caller]
+  pub fn provide<T: 'static>(&self, data: T) -> Self {
+    let cx = Self {
+      msg_dispatcher: self.msg_dispatcher.clone(),
+      data: Rc::new(ContextScope {
+        parent: Some(self.data.clone()),
+        ..Default::default()
+      }),
+      spawner: self.spawner.clone(),
+    };
+
+    if cx.set_context(data).is_err() {
+      unreachable!("new scopes to be empty");
     }
+
+    cx
   }
 
+  /// Sets a context which can be retrieved with [`Ctx::get_context`]
+  /// from this [`Ctx`], and any [`Ctx`] derived from it.
+  ///
+  /// Each type can only be set once per scope, but it can be
+  /// shadowed in a subtree with [`Ctx::provide`], or replaced with
+  /// [`Ctx::update_context`].
   #[track_caller]
   pub fn set_context<T: 'static>(
     &self,
@@ -66,7 +447,7 @@ impl<Msg> Ctx<Msg> {
 
     let type_id = data.type_id();
 
-    match self.data.borrow_mut().entry(type_id) {
+    match self.data.contexts.borrow_mut().entry(type_id) {
       Entry::Occupied(ocupied) => Err(ContextError::AlreadySet {
         data,
         #[cfg(debug_assertions)]
@@ -74,7 +455,7 @@ impl<Msg> Ctx<Msg> {
       }),
       Entry::Vacant(vacant) => {
         vacant.insert(ContextData {
-          data: Box::pin(AnyContainer(data)),
+          value: Observable::new(Rc::new(data)),
           #[cfg(debug_assertions)]
           location: std::panic::Location::caller(),
         });
@@ -84,25 +465,72 @@ impl<Msg> Ctx<Msg> {
     }
   }
 
-  pub fn get_context<T: 'static>(&self) -> Option<&T> {
-    let type_id = TypeId::of::<T>();
+  /// Replaces the context of type `T` in the innermost scope it was
+  /// set in, notifying every [`Ctx::context_stream`] of that scope.
+  pub fn update_context<T: 'static>(
+    &self,
+    data: T,
+  ) -> Result<(), ContextError<T>> {
+    match self.find_context(TypeId::of::<T>()) {
+      Some(value) => {
+        value.set(Rc::new(data));
+
+        Ok(())
+      }
+      None => Err(ContextError::NotSet { data }),
+    }
+  }
+
+  /// Gets the current value of the context of type `T` from the
+  /// innermost scope it was set in.
+  pub fn get_context<T: 'static>(&self) -> Option<Rc<T>> {
+    self
+      .find_context(TypeId::of::<T>())
+      .map(|value| downcast_context(value.get()))
+  }
 
-    if let Some(ContextData { data, .. }) = self.data.borrow().get(&type_id) {
-      let data_ptr = data.0.downcast_ref::<T>().unwrap() as *const T;
+  /// Returns a [`Stream`] which yields the current value of the
+  /// context of type `T`, followed by every value it is updated to.
+  ///
+  /// This can be passed to `dyn_child` and `dyn_text`, so that they
+  /// re-render when the context changes.
+  pub fn context_stream<T: 'static>(
+    &self,
+  ) -> Option<impl Stream<Item = Rc<T>> + 'static> {
+    self
+      .find_context(TypeId::of::<T>())
+      .map(|value| value.stream().map(downcast_context))
+  }
 
-      // Safety:
-      // This is safe because values can be set only once, and they
-      // are guaranteed to live for as long as `Ctx` exists.
-      // Since `data` is also `Pin`, there's no chance of the `Box<T>`
-      // being swapped out, avoiding the possiblity of this becoming
-      // a dangling pointer.
-      unsafe { Some(&*data_ptr) }
-    } else {
-      None
+  fn find_context(&self, type_id: TypeId) -> Option<Observable<Rc<dyn Any>>> {
+    let mut scope = &*self.data;
+
+    loop {
+      if let Some(ContextData { value, .. }) =
+        scope.contexts.borrow().get(&type_id)
+      {
+        return Some(value.clone());
+      }
+
+      scope = scope.parent.as_deref()?;
     }
   }
 }
 
+fn downcast_context<T: 'static>(value: Rc<dyn Any>) -> Rc<T> {
+  value
+    .downcast()
+    .unwrap_or_else(|_| unreachable!("contexts to be keyed by their type"))
+}
+
+/// The contexts set on a [`Ctx`] and the ones derived from it, which
+/// fall back to the scope it was provided from.
+#[derive(Default)]
+pub(crate) struct ContextScope {
+  contexts: RefCell<HashMap<TypeId, ContextData>>,
+  parent: Option<Rc<ContextScope>>,
+}
+
 #[derive(Debug, educe::Educe)]
 #[educe(Clone(bound))]
 pub enum ContextError<T = ()> {
@@ -113,45 +541,1528 @@ pub enum ContextError<T = ()> {
     #[cfg(debug_assertions)]
     location: &'static std::panic::Location<'static>,
   },
+  /// The context was never set on any scope of the [`Ctx`].
+  NotSet {
+    /// The data that was attempted to be set.
+    data: T,
+  },
 }
 
 pub(crate) struct ContextData {
-  data: Pin<Box<AnyContainer<dyn Any>>>,
+  /// The current value, which is replaced as a whole whenever the
+  /// context is updated, so it can be handed out without holding on
+  /// to a borrow.
+  value: Observable<Rc<dyn Any>>,
   #[cfg(debug_assertions)]
   location: &'static std::panic::Location<'static>,
 }
 
-struct AnyContainer<T: ?Sized>(T);
+type SubscriptionsFn<M, Msg> = Box<dyn Fn(&M) -> Sub<Msg>>;
+type UrgentFn<Msg> = Box<dyn Fn(&Msg) -> bool>;
+type UpdateErrorFn<Msg> = Box<dyn FnMut(UpdateError<Msg>) -> Option<Msg>>;
+
+/// Determines how often the view model is diffed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scheduling {
+  /// The view model is diffed after every message.
+  #[default]
+  Immediate,
+  /// Messages are applied as soon as they arrive, but the view model
+  /// is only diffed once per frame.
+  ///
+  /// On the web, frames are driven by `requestAnimationFrame`, and
+  /// natively, by `tick`.
+  Batched {
+    /// The time between frames when not running on the web.
+    tick: Duration,
+  },
+}
+
+impl Scheduling {
+  /// Batches messages at roughly 60 frames per second.
+  pub fn per_frame() -> Self {
+    Self::Batched {
+      tick: Duration::from_millis(16),
+    }
+  }
+}
+
+/// What happens to a pending [`AsyncUpdate`] when another message
+/// arrives.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PendingPolicy {
+  /// The message waits for the pending update to finish.
+  #[default]
+  Queue,
+  /// The pending update is cancelled, and the message is applied to
+  /// the model it was given instead.
+  Cancel,
+}
+
+type PendingPolicyFn<Msg> = Box<dyn Fn(&Msg, &Msg) -> PendingPolicy>;
+
+/// An [`AsyncUpdate`] which has yet to finish.
+struct PendingUpdate<M, Msg> {
+  fut: LocalBoxFuture<'static, UpdateResult<M, Msg>>,
+  msg: Msg,
+  /// The message passed to middleware once the update finishes.
+  applied_msg: Option<Msg>,
+  urgent: bool,
+}
+
+/// Something which woke up the runtime loop.
+enum Event<M, Msg> {
+  Msg(Msg),
+  Resolved(UpdateResult<M, Msg>),
+  Frame,
+  Idle(utils::IdlePeriod),
+  #[cfg(feature = "time-travel")]
+  TimeTravel(time_travel::Control),
+  #[cfg(feature = "hot-reload")]
+  Reload,
+  #[cfg(feature = "persistence")]
+  Save,
+}
+
+/// A handle which can be used to stop a running [`Runtime`].
+#[derive(educe::Educe)]
+#[educe(Clone)]
+pub struct StopHandle<Msg>(UnboundedSender<Msg>);
 
-impl<T: ?Sized> Unpin for AnyContainer<T> {}
+impl<Msg> StopHandle<Msg> {
+  /// Stops the runtime.
+  ///
+  /// Messages which were already queued are still processed, after
+  /// which the runtime stops and all subscriptions are cancelled.
+  pub fn stop(&self) {
+    self.0.close_channel();
+  }
+
+  /// Returns `true` if the runtime was stopped.
+  pub fn is_stopped(&self) -> bool {
+    self.0.is_closed()
+  }
+}
 
-#[derive(derive_more::Constructor)]
 pub struct Runtime<M: DiffableModel, Msg, UF> {
   model: Option<M>,
   view_model: M::ViewModel,
   update_fn: UF,
+  subscriptions_fn: Option<SubscriptionsFn<M, Msg>>,
+  /// The currently running subscriptions.
+  active_subs: HashMap<SubId, AbortHandle>,
+  /// Drives the effects, subscriptions and view tasks of the app.
+  executor: RefCell<utils::Executor>,
+  spawner: utils::Spawner,
+  scheduling: Scheduling,
+  /// Messages for which the view model is diffed immediately, even
+  /// when batching.
+  urgent_fn: Option<UrgentFn<Msg>>,
+  pipeline: Option<Pipeline<M, Msg>>,
+  update_error_fn: Option<UpdateErrorFn<Msg>>,
+  /// The async update which is running, if any.
+  pending: Option<PendingUpdate<M, Msg>>,
+  pending_policy_fn: Option<PendingPolicyFn<Msg>>,
+  /// Set when the model changed, but the view model has yet to
+  /// be diffed.
+  dirty: bool,
+  /// The next frame, when one was requested.
+  frame: Option<LocalBoxFuture<'static, ()>>,
+  /// The next idle period, when messages are waiting for one.
+  idle: Option<LocalBoxFuture<'static, utils::IdlePeriod>>,
+  msg_sender: UnboundedSender<Msg>,
   msg_receiver: UnboundedReceiver<Msg>,
+  /// The receivers of bounded senders, which are only read once the
+  /// runtime is ready to process their next message.
+  bounded_receivers: Vec<Receiver<Msg>>,
+  /// Messages which were received, but not yet processed.
+  msg_queue: MsgQueue<Msg>,
+  #[cfg(feature = "time-travel")]
+  history: Option<time_travel::History<M, Msg>>,
+  #[cfg(feature = "hot-reload")]
+  reloader: Option<Reloader<M>>,
+  #[cfg(feature = "persistence")]
+  persistence: Option<Persistence<M>>,
+  #[cfg(feature = "recording")]
+  recorder: Option<recording::Recorder<M, Msg>>,
+  /// Set when replaying a recorded session, whose messages already
+  /// include the ones sent by effects and subscriptions, so those are
+  /// not run.
+  #[cfg(feature = "recording")]
+  replaying: bool,
 }
 
-impl<M: DiffableModel, Msg, UF> Runtime<M, Msg, UF>
-where
-  UF: FnMut(M, Msg) -> M,
-{
-  #[cfg(all(target_arch = "wasm32", feature = "web"))]
-  pub async fn run(&mut self) -> ! {
-    #[cfg(debug_assertions)]
-    assert!(self.model.is_some());
+impl<M: DiffableModel, Msg, UF> Runtime<M, Msg, UF> {
+  pub fn new(
+    model: Option<M>,
+    view_model: M::ViewModel,
+    update_fn: UF,
+    msg_sender: UnboundedSender<Msg>,
+    msg_receiver: UnboundedReceiver<Msg>,
+    executor: utils::Executor,
+  ) -> Self {
+    Self {
+      model,
+      view_model,
+      update_fn,
+      subscriptions_fn: None,
+      active_subs: Default::default(),
+      spawner: executor.spawner(),
+      executor: RefCell::new(executor),
+      scheduling: Default::default(),
+      urgent_fn: None,
+      pipeline: None,
+      update_error_fn: None,
+      pending: None,
+      pending_policy_fn: None,
+      dirty: false,
+      frame: None,
+      idle: None,
+      msg_sender,
+      msg_receiver,
+      bounded_receivers: vec![],
+      msg_queue: Default::default(),
+      #[cfg(feature = "time-travel")]
+      history: None,
+      #[cfg(feature = "hot-reload")]
+      reloader: None,
+      #[cfg(feature = "persistence")]
+      persistence: None,
+      #[cfg(feature = "recording")]
+      recorder: None,
+      #[cfg(feature = "recording")]
+      replaying: false,
+    }
+  }
 
-    loop {
-      if let Some(msg) = self.msg_receiver.next().await {
-        let model = self.model.take().unwrap();
+  pub fn set_subscriptions_fn(&mut self, f: SubscriptionsFn<M, Msg>) {
+    self.subscriptions_fn = Some(f);
+  }
+
+  pub fn set_scheduling(&mut self, scheduling: Scheduling) {
+    self.scheduling = scheduling;
+  }
+
+  pub fn set_urgent_fn(&mut self, f: UrgentFn<Msg>) {
+    self.urgent_fn = Some(f);
+  }
+
+  pub fn add_middleware(&mut self, middleware: Box<dyn Middleware<M, Msg>>)
+  where
+    M: Clone,
+    Msg: Clone,
+  {
+    self
+      .pipeline
+      .get_or_insert_with(Pipeline::new)
+      .push(middleware);
+  }
+
+  pub fn set_priority_fn(&mut self, f: PriorityFn<Msg>) {
+    self.msg_queue.set_priority_fn(f);
+  }
+
+  pub fn set_update_error_fn(&mut self, f: UpdateErrorFn<Msg>) {
+    self.update_error_fn = Some(f);
+  }
+
+  pub fn set_pending_policy_fn(&mut self, f: PendingPolicyFn<Msg>) {
+    self.pending_policy_fn = Some(f);
+  }
+
+  pub fn stop_handle(&self) -> StopHandle<Msg> {
+    StopHandle(self.msg_sender.clone())
+  }
+
+  #[cfg(feature = "hot-reload")]
+  pub(crate) fn set_reloader(&mut self, reloader: Reloader<M>) {
+    self.reloader = Some(reloader);
+  }
+
+  /// Saves the model with `persistence` after every update from now on.
+  #[cfg(feature = "persistence")]
+  pub(crate) fn set_persistence(&mut self, persistence: Persistence<M>) {
+    self.persistence = Some(persistence);
+  }
+
+  /// Saves the model right away, if a save was scheduled.
+  #[cfg(feature = "persistence")]
+  pub(crate) fn save_pending(&mut self) {
+    if let Some(persistence) = &mut self.persistence {
+      persistence.save_pending(self.model.as_ref().unwrap());
+    }
+  }
+
+  /// Starts recording every processed message.
+  #[cfg(feature = "time-travel")]
+  pub fn enable_time_travel(&mut self)
+  where
+    M: Clone,
+    Msg: Clone,
+  {
+    self.history = Some(time_travel::History::new(self.model()));
+  }
+
+  #[cfg(feature = "time-travel")]
+  pub fn time_travel_handle(&self) -> Option<TimeTravelHandle<Msg>> {
+    self.history.as_ref().map(time_travel::History::handle)
+  }
+
+  /// Records a session once the runtime starts running.
+  #[cfg(feature = "recording")]
+  pub fn enable_recording(&mut self)
+  where
+    M: Clone,
+    Msg: Clone,
+  {
+    self.recorder = Some(recording::Recorder::new());
+  }
+
+  #[cfg(feature = "recording")]
+  pub fn recording_handle(&self) -> Option<RecordingHandle<M, Msg>> {
+    self.recorder.as_ref().map(recording::Recorder::handle)
+  }
+
+  /// Stops running effects and subscriptions, as their messages are
+  /// part of the session being replayed.
+  #[cfg(feature = "recording")]
+  pub(crate) fn set_replaying(&mut self) {
+    self.replaying = true;
+  }
+
+  /// Returns `true` if effects and subscriptions should not run.
+  fn is_replaying(&self) -> bool {
+    #[cfg(feature = "recording")]
+    return self.replaying;
+
+    #[cfg(not(feature = "recording"))]
+    false
+  }
+
+  /// Returns `true` if live messages are being held back.
+  fn is_paused(&self) -> bool {
+    #[cfg(feature = "time-travel")]
+    if let Some(history) = &self.history {
+      return history.is_paused();
+    }
+
+    false
+  }
+
+  pub(crate) fn msg_sender(&self) -> &UnboundedSender<Msg> {
+    &self.msg_sender
+  }
+
+  pub(crate) fn take_model(&mut self) -> Option<M> {
+    self.model.take()
+  }
+
+  pub(crate) fn model(&self) -> &M {
+    self.model.as_ref().unwrap()
+  }
+
+  pub(crate) fn view_model(&self) -> &M::ViewModel {
+    &self.view_model
+  }
+
+  /// Takes the most urgent queued message, if any, without waiting.
+  ///
+  /// Idle messages are only taken when nothing else is queued.
+  pub(crate) fn try_next_msg(&mut self) -> Option<Msg> {
+    if self.is_paused() {
+      return None;
+    }
+
+    let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+
+    self.receive_msgs(&mut cx);
+
+    self.pop_msg(&mut cx).or_else(|| {
+      self
+        .pending
+        .is_none()
+        .then(|| self.msg_queue.pop_idle())
+        .flatten()
+    })
+  }
+
+  /// Takes the most urgent message which is not waiting for an idle
+  /// period, unless it has to wait for the pending update to finish.
+  fn pop_msg(&mut self, cx: &mut task::Context) -> Option<Msg> {
+    self.receive_bounded_msg(cx);
 
-        let new_model = (self.update_fn)(model, msg);
+    if let Some(pending) = &self.pending {
+      let next = self.msg_queue.peek()?;
 
-        new_model.diff(&mut self.view_model);
+      let policy = self
+        .pending_policy_fn
+        .as_ref()
+        .map_or(PendingPolicy::Queue, |f| f(&pending.msg, next));
 
-        self.model = Some(new_model);
+      if policy == PendingPolicy::Queue {
+        return None;
+      }
+
+      self.cancel_pending();
+    }
+
+    self.msg_queue.pop()
+  }
+
+  /// Drops the pending update, leaving the model as it was before.
+  fn cancel_pending(&mut self) {
+    if self.pending.take().is_some() {
+      debug!("pending update cancelled");
+
+      // Replaying the message would apply it
+      #[cfg(feature = "recording")]
+      if let Some(recorder) = &self.recorder {
+        recorder.forget_last();
+      }
+
+      #[cfg(feature = "time-travel")]
+      if let Some(history) = &mut self.history {
+        history.forget_last();
+      }
+    }
+  }
+
+  /// Moves the messages which were sent so far into the queue,
+  /// returning `true` once the runtime was stopped.
+  fn receive_msgs(&mut self, cx: &mut task::Context) -> bool {
+    loop {
+      match self.msg_receiver.poll_next_unpin(cx) {
+        Poll::Ready(Some(msg)) => self.msg_queue.push(msg),
+        Poll::Ready(None) => break,
+        Poll::Pending => return false,
       }
     }
+
+    // Messages which bounded senders already queued are still
+    // processed, but no more can be sent
+    for mut receiver in self.bounded_receivers.drain(..) {
+      receiver.close();
+
+      while let Ok(Some(msg)) = receiver.try_next() {
+        self.msg_queue.push(msg);
+      }
+    }
+
+    true
+  }
+
+  /// Takes a single message from the bounded senders, once no other
+  /// message is waiting to be processed, so their queues only free up
+  /// as the runtime gets through them.
+  fn receive_bounded_msg(&mut self, cx: &mut task::Context) {
+    if self.msg_queue.has_non_idle() {
+      return;
+    }
+
+    let mut i = 0;
+
+    while i < self.bounded_receivers.len() {
+      match self.bounded_receivers[i].poll_next_unpin(cx) {
+        Poll::Ready(Some(msg)) => {
+          self.msg_queue.push(msg);
+
+          // The other senders go first next time
+          let receiver = self.bounded_receivers.remove(i);
+
+          self.bounded_receivers.push(receiver);
+
+          return;
+        }
+        // Every sender was dropped
+        Poll::Ready(None) => {
+          self.bounded_receivers.remove(i);
+        }
+        Poll::Pending => i += 1,
+      }
+    }
+  }
+
+  /// Reads messages from `receiver` one at a time, as the runtime
+  /// gets to them.
+  pub(crate) fn add_bounded_receiver(&mut self, receiver: Receiver<Msg>) {
+    self.bounded_receivers.push(receiver);
+  }
+
+  /// Polls the effects, subscriptions and view tasks, without
+  /// waiting.
+  pub(crate) fn poll_tasks(&self) {
+    self.executor.borrow_mut().run_until_stalled();
+  }
+
+  /// Polls the effects, subscriptions and view tasks, waking `cx`
+  /// once they can make more progress.
+  #[cfg(feature = "ssr")]
+  pub(crate) fn poll_tasks_with(&self, cx: &mut task::Context) {
+    self.executor.borrow_mut().poll(cx);
+  }
+}
+
+impl<M: DiffableModel, Msg: 'static, UF, U> Runtime<M, Msg, UF>
+where
+  UF: FnMut(M, Msg) -> U,
+  U: IntoUpdate<M, Msg>,
+{
+  #[cfg(all(target_arch = "wasm32", feature = "web"))]
+  pub async fn run(&mut self) {
+    self.run_until_stopped().await
+  }
+
+  /// Runs the message loop until the runtime is stopped.
+  ///
+  /// Timers, such as the ones used for batching, are driven by tokio
+  /// when called from within a tokio runtime.
+  #[cfg(all(not(target_arch = "wasm32"), feature = "ssr"))]
+  pub async fn run(&mut self) {
+    self.run_until_stopped().await
+  }
+
+  /// Runs the message loop until the runtime is stopped, or, with
+  /// hot-reloading, until a new build is available.
+  ///
+  /// Effects and subscriptions are driven by the returned future
+  /// itself, so this can be used with any executor.
+  pub async fn run_until_stopped(&mut self) {
+    #[cfg(debug_assertions)]
+    assert!(self.model.is_some());
+
+    self.start();
+
+    while let Some(event) = future::poll_fn(|cx| {
+      self.executor.get_mut().poll(cx);
+
+      #[cfg(feature = "time-travel")]
+      if let Some(history) = &mut self.history {
+        if let Poll::Ready(control) = history.poll_control(cx) {
+          return Poll::Ready(Some(Event::TimeTravel(control)));
+        }
+      }
+
+      if let Some(pending) = &mut self.pending {
+        if let Poll::Ready(update) = pending.fut.poll_unpin(cx) {
+          return Poll::Ready(Some(Event::Resolved(update)));
+        }
+      }
+
+      // Queued messages are drained before the next frame, so
+      // they all end up in the same diff
+      if !self.is_paused() {
+        let stopped = self.receive_msgs(cx);
+
+        if let Some(msg) = self.pop_msg(cx) {
+          return Poll::Ready(Some(Event::Msg(msg)));
+        }
+
+        // Messages waiting for an idle period, or for the pending
+        // update, are still processed before stopping
+        if stopped && self.pending.is_none() {
+          return Poll::Ready(self.msg_queue.pop_idle().map(Event::Msg));
+        }
+      }
+
+      #[cfg(feature = "hot-reload")]
+      if let Some(reloader) = &mut self.reloader {
+        if let Poll::Ready(()) = reloader.poll_reload(cx) {
+          return Poll::Ready(Some(Event::Reload));
+        }
+      }
+
+      if let Some(frame) = &mut self.frame {
+        if let Poll::Ready(()) = frame.poll_unpin(cx) {
+          return Poll::Ready(Some(Event::Frame));
+        }
+      }
+
+      #[cfg(feature = "persistence")]
+      if let Some(persistence) = &mut self.persistence {
+        if let Poll::Ready(()) = persistence.poll_save(cx) {
+          return Poll::Ready(Some(Event::Save));
+        }
+      }
+
+      if !self.is_paused()
+        && self.pending.is_none()
+        && self.msg_queue.has_idle()
+      {
+        let idle = self.idle.get_or_insert_with(utils::next_idle_period);
+
+        if let Poll::Ready(period) = idle.poll_unpin(cx) {
+          self.idle = None;
+
+          return Poll::Ready(Some(Event::Idle(period)));
+        }
+      }
+
+      Poll::Pending
+    })
+    .await
+    {
+      match event {
+        Event::Msg(msg) => self.step(msg),
+        Event::Resolved(update) => self.resolve(update),
+        Event::Frame => self.flush(),
+        Event::Idle(period) => self.step_idle(&period),
+        #[cfg(feature = "time-travel")]
+        Event::TimeTravel(control) => self.time_travel(control),
+        #[cfg(feature = "hot-reload")]
+        Event::Reload => {
+          if let Some(reloader) = &self.reloader {
+            reloader.reload(self.model.as_ref().unwrap());
+          }
+
+          break;
+        }
+        #[cfg(feature = "persistence")]
+        Event::Save => self.save_pending(),
+      }
+    }
+
+    self.flush();
+
+    #[cfg(feature = "persistence")]
+    self.save_pending();
+
+    self.cancel_subscriptions();
+
+    // Aborted tasks are only dropped once polled, so their streams
+    // would otherwise stay open until the app is
+    self.executor.get_mut().run_until_stalled();
+  }
+
+  /// Processes a single message, diffing the view model right away
+  /// unless batching.
+  pub(crate) fn step(&mut self, msg: Msg) {
+    let _span =
+      tracing::debug_span!("message", msg = any::type_name::<Msg>()).entered();
+
+    // Messages are recorded as received, so replaying them goes
+    // through the same middleware
+    #[cfg(feature = "recording")]
+    if let Some(recorder) = &self.recorder {
+      recorder.record(&msg);
+    }
+
+    let msg = match &mut self.pipeline {
+      Some(pipeline) => {
+        match pipeline.before_update(self.model.as_ref().unwrap(), msg) {
+          Some(msg) => msg,
+          None => return,
+        }
+      }
+      None => msg,
+    };
+
+    let urgent = self.urgent_fn.as_ref().is_some_and(|f| f(&msg));
+
+    // Middleware needs the message after it was consumed by the update
+    let applied_msg = self
+      .pipeline
+      .as_ref()
+      .map(|pipeline| pipeline.clone_msg(&msg));
+
+    #[cfg(feature = "time-travel")]
+    if let Some(history) = &mut self.history {
+      history.record(&msg);
+    }
+
+    let model = self.model.take().unwrap();
+
+    // Middleware is also given the old model
+    let backup = U::backup(&model, &msg).or_else(|| {
+      self
+        .pipeline
+        .as_ref()
+        .map(|pipeline| pipeline.backup(&model, &msg))
+    });
+
+    let update = tracing::debug_span!("update")
+      .in_scope(|| (self.update_fn)(model, msg).into_update());
+
+    match update {
+      Update::Ready(update) => self.commit(update, backup, applied_msg, urgent),
+      // The previous model is kept until the update finishes
+      Update::Pending(fut) => {
+        let (model, msg) =
+          backup.expect("`IntoUpdate::backup` to back up async updates");
+
+        self.model = Some(model);
+
+        self.pending = Some(PendingUpdate {
+          fut,
+          msg,
+          applied_msg,
+          urgent,
+        });
+      }
+    }
+  }
+
+  /// Finishes the pending update with its result.
+  fn resolve(&mut self, update: UpdateResult<M, Msg>) {
+    let Some(PendingUpdate {
+      msg,
+      applied_msg,
+      urgent,
+      ..
+    }) = self.pending.take()
+    else {
+      return;
+    };
+
+    let _span =
+      tracing::debug_span!("resolved_update", msg = any::type_name::<Msg>())
+        .entered();
+
+    #[cfg(feature = "time-travel")]
+    if let (Some(history), Ok((model, _))) = (&mut self.history, &update) {
+      history.resolved(model);
+    }
+
+    let model = self.model.take().unwrap();
+
+    self.commit(update, Some((model, msg)), applied_msg, urgent);
+  }
+
+  /// Replaces the model with the result of an update, or, if it
+  /// failed, restores the `backup` and reports the error.
+  fn commit(
+    &mut self,
+    update: UpdateResult<M, Msg>,
+    backup: Option<(M, Msg)>,
+    applied_msg: Option<Msg>,
+    urgent: bool,
+  ) {
+    let (new_model, cmd) = match update {
+      Ok(update) => update,
+      Err(report) => {
+        let (model, msg) =
+          backup.expect("`IntoUpdate::backup` to back up fallible updates");
+
+        #[cfg(feature = "time-travel")]
+        if let Some(history) = &mut self.history {
+          history.snapshot(&model);
+        }
+
+        self.model = Some(model);
+
+        self.handle_update_error(UpdateError { msg, report });
+
+        return;
+      }
+    };
+
+    if let (Some(pipeline), Some(msg), Some((old_model, _))) =
+      (&mut self.pipeline, applied_msg, &backup)
+    {
+      pipeline.after_update(&msg, old_model, &new_model);
+    }
+
+    #[cfg(feature = "time-travel")]
+    if let Some(history) = &mut self.history {
+      history.snapshot(&new_model);
+    }
+
+    #[cfg(feature = "persistence")]
+    if let Some(persistence) = &mut self.persistence {
+      persistence.schedule_save();
+    }
+
+    self.model = Some(new_model);
+
+    self.dirty = true;
+
+    self.spawn_cmd(cmd);
+
+    match self.scheduling {
+      Scheduling::Batched { tick } if !urgent => {
+        if self.frame.is_none() {
+          self.frame = Some(utils::next_frame(tick));
+        }
+      }
+      _ => self.flush(),
+    }
+  }
+
+  /// Processes idle messages until `period` is over, or until a more
+  /// urgent message arrives.
+  fn step_idle(&mut self, period: &utils::IdlePeriod) {
+    let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+
+    while period.has_time_remaining()
+      && !self.is_paused()
+      && self.pending.is_none()
+    {
+      self.receive_msgs(&mut cx);
+
+      // More urgent messages are left for the message loop
+      if self.msg_queue.has_non_idle() {
+        break;
+      }
+
+      match self.msg_queue.pop_idle() {
+        Some(msg) => self.step(msg),
+        None => break,
+      }
+    }
+  }
+
+  /// Polls the pending update once, without waiting, finishing it if
+  /// it resolved.
+  pub(crate) fn poll_pending(&mut self) {
+    let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+
+    if let Some(pending) = &mut self.pending {
+      if let Poll::Ready(update) = pending.fut.poll_unpin(&mut cx) {
+        self.resolve(update);
+      }
+    }
+  }
+
+  /// Passes `err` to the error handler, or logs it if there is none.
+  fn handle_update_error(&mut self, err: UpdateError<Msg>) {
+    // The message sent in response was recorded as well
+    let replaying = self.is_replaying();
+
+    match &mut self.update_error_fn {
+      Some(f) => {
+        if let Some(msg) = f(err).filter(|_| !replaying) {
+          let _ = self.msg_sender.unbounded_send(msg);
+        }
+      }
+      None => {
+        tracing::error!(
+          msg = any::type_name::<Msg>(),
+          "update failed: {err:?}"
+        );
+      }
+    }
+  }
+
+  /// Diffs the view model if the model changed since the last diff.
+  pub(crate) fn flush(&mut self) {
+    self.frame = None;
+
+    if self.dirty {
+      self.dirty = false;
+
+      tracing::debug_span!("diff")
+        .in_scope(|| self.model.as_ref().unwrap().diff(&mut self.view_model));
+
+      self.update_subscriptions();
+    }
+  }
+
+  /// Applies the time-travel commands which were already sent, without
+  /// waiting.
+  #[cfg(all(
+    feature = "time-travel",
+    not(all(target_arch = "wasm32", feature = "web"))
+  ))]
+  pub(crate) fn apply_time_travel_controls(&mut self) {
+    while let Some(control) = self
+      .history
+      .as_mut()
+      .and_then(|history| history.try_next_control())
+    {
+      self.time_travel(control);
+    }
+  }
+
+  #[cfg(feature = "time-travel")]
+  fn time_travel(&mut self, control: time_travel::Control) {
+    use time_travel::Control;
+
+    // Jumps replace the model the pending update was given
+    if let Control::JumpTo(_) | Control::StepBy(_) | Control::Toggle(_) =
+      control
+    {
+      self.cancel_pending();
+    }
+
+    let Some(history) = &mut self.history else {
+      return;
+    };
+
+    let mut toggled = None;
+
+    let step = match control {
+      Control::JumpTo(step) => step,
+      Control::StepBy(delta) => {
+        history.current_step().saturating_add_signed(delta)
+      }
+      Control::Toggle(index) => {
+        history.toggle(index);
+
+        toggled = Some(index);
+
+        history.current_step()
+      }
+      Control::Pause | Control::Resume => {
+        history.set_paused(matches!(control, Control::Pause));
+
+        return;
+      }
+    };
+
+    let update_fn = &mut self.update_fn;
+
+    // The effects of replayed messages already happened, so their
+    // commands are dropped
+    let mut replay = |model, msg, resolved| {
+      let backup = U::backup(&model, &msg);
+
+      match (update_fn(model, msg).into_update(), resolved) {
+        (Update::Ready(Ok((model, _))), _) => model,
+        // Async updates are not run again, as they might resolve
+        // differently, so the model they resolved to is reused
+        (Update::Pending(_), Some(resolved)) => resolved,
+        // Failed updates kept the previous model the first time around
+        _ => {
+          backup
+            .expect("`IntoUpdate::backup` to back up fallible updates")
+            .0
+        }
+      }
+    };
+
+    let model = history.rebuild(step, &mut replay);
+
+    if let Some(pipeline) = &mut self.pipeline {
+      // Toggling a step changes the model of every step after it
+      if let Some(index) = toggled {
+        let models = history.replay_from(index + 1, replay);
+
+        pipeline.after_toggle(index + 1, &models);
+      }
+
+      pipeline.after_time_travel(&model);
+    }
+
+    self.model = Some(model);
+
+    self.dirty = true;
+
+    self.flush();
+  }
+
+  /// Feeds every item of `stream` into the message queue, for as long
+  /// as the runtime is running.
+  pub(crate) fn forward_stream(
+    &mut self,
+    stream: impl Stream<Item = Msg> + 'static,
+  ) {
+    let msg_sender = self.msg_sender.clone();
+
+    self.spawner.spawn(stream.for_each(move |msg| {
+      let _ = msg_sender.unbounded_send(msg);
+
+      async {}
+    }));
+  }
+
+  /// Spawns each effect of `cmd`, feeding the resulting messages
+  /// back into the message queue.
+  fn spawn_cmd(&self, cmd: Cmd<Msg>) {
+    if self.is_replaying() {
+      return;
+    }
+
+    for fut in cmd.0 {
+      let msg_sender = self.msg_sender.clone();
+
+      self.spawner.spawn(async move {
+        if let Some(msg) = fut.await {
+          let _ = msg_sender.unbounded_send(msg);
+        }
+      });
+    }
+  }
+
+  /// Prepares the runtime for processing messages, starting the
+  /// recorded session from the model as it is now.
+  pub(crate) fn start(&mut self) {
+    #[cfg(feature = "recording")]
+    if let Some(recorder) = &self.recorder {
+      recorder.start(self.model.as_ref().unwrap());
+    }
+
+    self.update_subscriptions();
+  }
+
+  /// Re-evaluates the subscriptions for the current model, starting
+  /// the ones which are new, and cancelling the ones which are no
+  /// longer present.
+  pub(crate) fn update_subscriptions(&mut self) {
+    let Some(subscriptions_fn) = &self.subscriptions_fn else {
+      return;
+    };
+
+    if self.is_replaying() {
+      return;
+    }
+
+    let subs = subscriptions_fn(self.model.as_ref().unwrap());
+
+    let mut still_active = HashMap::with_capacity(subs.0.len());
+
+    for (id, stream_fn) in subs.0 {
+      if still_active.contains_key(&id) {
+        continue;
+      }
+
+      if let Some(handle) = self.active_subs.remove(&id) {
+        still_active.insert(id, handle);
+      } else {
+        let msg_sender = self.msg_sender.clone();
+
+        let (fut, handle) =
+          future::abortable(stream_fn().for_each(move |msg| {
+            let _ = msg_sender.unbounded_send(msg);
+
+            async {}
+          }));
+
+        self.spawner.spawn(fut.map(|_| ()));
+
+        still_active.insert(id, handle);
+      }
+    }
+
+    self.cancel_subscriptions();
+
+    self.active_subs = still_active;
+  }
+
+  fn cancel_subscriptions(&mut self) {
+    for (_, handle) in self.active_subs.drain() {
+      handle.abort();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+  use std::{cell::Cell, rc::Rc};
+
+  struct Model {
+    listening: bool,
+  }
+
+  impl DiffableModel for Model {
+    type ViewModel = ();
+
+    fn to_view_model(&self) -> Self::ViewModel {}
+
+    fn diff(&self, _: &mut Self::ViewModel) {}
+  }
+
+  #[test]
+  fn subscriptions_follow_the_model() {
+    let starts = Rc::new(Cell::new(0));
+
+    let mut app = App::new(
+      || Model { listening: false },
+      |model, ()| model,
+      |_, cx: Ctx<()>| div(cx),
+    )
+    .subscriptions(clone!([starts], move |model: &Model| {
+      if !model.listening {
+        return Sub::none();
+      }
+
+      Sub::stream(
+        "ticks",
+        clone!([starts], move || {
+          starts.set(starts.get() + 1);
+
+          futures::stream::pending()
+        }),
+      )
+    }));
+
+    let rt = &mut app.rt;
+
+    rt.update_subscriptions();
+
+    assert!(rt.active_subs.is_empty());
+
+    rt.model = Some(Model { listening: true });
+    rt.update_subscriptions();
+    rt.update_subscriptions();
+
+    // The running subscription is kept across updates
+    assert_eq!(starts.get(), 1);
+
+    rt.model = Some(Model { listening: false });
+    rt.update_subscriptions();
+
+    assert!(rt.active_subs.is_empty());
+
+    // Subscriptions which were cancelled start over
+    rt.model = Some(Model { listening: true });
+    rt.update_subscriptions();
+
+    assert_eq!(starts.get(), 2);
+  }
+
+  #[test]
+  fn counter_behaviour() {
+    use futures::channel::mpsc::{
+      unbounded, UnboundedReceiver, UnboundedSender,
+    };
+    use std::cell::RefCell;
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Increment,
+      Decrement,
+    }
+
+    struct Counter(i32);
+
+    struct CounterViewModel {
+      count: UnboundedSender<i32>,
+      count_stream: RefCell<Option<UnboundedReceiver<i32>>>,
+    }
+
+    impl DiffableModel for Counter {
+      type ViewModel = CounterViewModel;
+
+      fn to_view_model(&self) -> Self::ViewModel {
+        let (tx, rx) = unbounded();
+
+        tx.unbounded_send(self.0).unwrap();
+
+        CounterViewModel {
+          count: tx,
+          count_stream: RefCell::new(Some(rx)),
+        }
+      }
+
+      fn diff(&self, view_model: &mut Self::ViewModel) {
+        view_model.count.unbounded_send(self.0).unwrap();
+      }
+    }
+
+    let mut app = TestApp::new(App::new(
+      || Counter(0),
+      |Counter(count), msg| match msg {
+        Msg::Increment => Counter(count + 1),
+        Msg::Decrement => Counter(count - 1),
+      },
+      |vm: &CounterViewModel, cx: Ctx<Msg>| {
+        p(cx).dyn_text(vm.count_stream.borrow_mut().take().unwrap())
+      },
+    ));
+
+    app.dispatch(Msg::Increment);
+    app.dispatch(Msg::Increment);
+    app.dispatch(Msg::Decrement);
+
+    assert_eq!(app.run_until_idle(), 3);
+    assert_eq!(app.model().0, 1);
+    assert_eq!(
+      app.messages(),
+      [Msg::Increment, Msg::Increment, Msg::Decrement]
+    );
+    assert_eq!(
+      app.render_to_string(),
+      "<p><!-- <DynChild> -->1<!-- </DynChild> --></p>"
+    );
+  }
+
+  #[test]
+  fn derived_diff_only_notifies_changed_fields() {
+    use futures::{FutureExt, Stream, StreamExt};
+
+    #[derive(DiffableModel)]
+    struct Todo {
+      title: String,
+      done: bool,
+    }
+
+    #[derive(DiffableModel)]
+    struct Model {
+      filter: String,
+      #[diffable(skip)]
+      _cache: Vec<u8>,
+      #[diffable(nested)]
+      todos: Vec<Todo>,
+    }
+
+    fn next<T>(stream: &mut (impl Stream<Item = T> + Unpin)) -> Option<T> {
+      stream.next().now_or_never().flatten()
+    }
+
+    let todo = |title: &str, done| Todo {
+      title: title.to_string(),
+      done,
+    };
+
+    let model = Model {
+      filter: "all".to_string(),
+      _cache: vec![],
+      todos: vec![todo("a", false), todo("b", false)],
+    };
+
+    let mut view_model = model.to_view_model();
+
+    let mut filter = Box::pin(view_model.filter.stream());
+    let mut len = Box::pin(view_model.todos.len.stream());
+    let mut a_done = Box::pin(view_model.todos.items[0].done.stream());
+    let mut b_title = Box::pin(view_model.todos.items[1].title.stream());
+
+    assert_eq!(next(&mut filter).as_deref(), Some("all"));
+    assert_eq!(next(&mut len), Some(2));
+    assert_eq!(next(&mut a_done), Some(false));
+    assert_eq!(next(&mut b_title).as_deref(), Some("b"));
+
+    let model = Model {
+      todos: vec![todo("a", true), todo("b", false), todo("c", false)],
+      ..model
+    };
+
+    model.diff(&mut view_model);
+
+    assert_eq!(next(&mut filter), None);
+    assert_eq!(next(&mut len), Some(3));
+    assert_eq!(next(&mut a_done), Some(true));
+    assert_eq!(next(&mut b_title), None);
+    assert_eq!(view_model.todos.items[2].title.get(), "c");
+  }
+
+  #[test]
+  fn batched_scheduling_diffs_once_per_frame() {
+    use std::cell::Cell;
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Input,
+      Submit,
+    }
+
+    struct Model;
+
+    impl DiffableModel for Model {
+      type ViewModel = Cell<usize>;
+
+      fn to_view_model(&self) -> Self::ViewModel {
+        Cell::new(0)
+      }
+
+      fn diff(&self, diffs: &mut Self::ViewModel) {
+        diffs.set(diffs.get() + 1);
+      }
+    }
+
+    let mut app = TestApp::new(
+      App::new(|| Model, |model, _| model, |_, cx: Ctx<Msg>| div(cx))
+        .scheduling(Scheduling::per_frame())
+        .urgent_if(|msg| *msg == Msg::Submit),
+    );
+
+    app.dispatch(Msg::Input);
+    app.dispatch(Msg::Input);
+    app.dispatch(Msg::Input);
+
+    assert_eq!(app.run_until_idle(), 3);
+    assert_eq!(app.view_model().get(), 1);
+
+    app.dispatch(Msg::Input);
+    app.dispatch(Msg::Submit);
+
+    assert!(app.step());
+    assert_eq!(app.view_model().get(), 1);
+    assert!(app.step());
+    assert_eq!(app.view_model().get(), 2);
+  }
+
+  #[test]
+  fn failed_updates_keep_the_model_and_report_errors() {
+    use error_stack::{report, Report};
+
+    #[derive(Debug, derive_more::Display)]
+    #[display(fmt = "insufficient funds")]
+    struct InsufficientFunds;
+
+    impl error_stack::Context for InsufficientFunds {}
+
+    #[derive(Clone, DiffableModel)]
+    struct Account {
+      balance: u32,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Withdraw(u32),
+      Declined(u32),
+    }
+
+    let mut app = TestApp::new(
+      App::new(
+        || Account { balance: 10 },
+        |account: Account, msg| -> Result<Account, Report<InsufficientFunds>> {
+          match msg {
+            Msg::Withdraw(amount) => Ok(Account {
+              balance: account
+                .balance
+                .checked_sub(amount)
+                .ok_or_else(|| report!(InsufficientFunds))?,
+            }),
+            Msg::Declined(_) => Ok(account),
+          }
+        },
+        |vm: &AccountViewModel, cx| p(cx).dyn_text(&vm.balance),
+      )
+      .on_update_error(|err| {
+        assert!(err.report::<InsufficientFunds>().is_some());
+
+        match err.into_failed_msg() {
+          Msg::Withdraw(amount) => Some(Msg::Declined(amount)),
+          _ => None,
+        }
+      }),
+    );
+
+    app.dispatch(Msg::Withdraw(4));
+    app.dispatch(Msg::Withdraw(7));
+    app.run_until_idle();
+
+    assert_eq!(app.model().balance, 6);
+    assert_eq!(
+      app.messages(),
+      [Msg::Withdraw(4), Msg::Withdraw(7), Msg::Declined(7)]
+    );
+  }
+
+  #[test]
+  fn provided_contexts_are_scoped_to_subtrees() {
+    #[derive(Debug)]
+    struct Theme(&'static str);
+
+    struct Locale(&'static str);
+
+    fn themed(cx: Ctx<()>) -> View<()> {
+      let theme = cx.get_context::<Theme>().unwrap().0;
+      let locale = cx.get_context::<Locale>().map_or("none", |l| l.0);
+
+      p(cx).attr("theme", theme).text(locale).into_view()
+    }
+
+    let app = App::new(
+      || (),
+      |_, _| (),
+      |_, cx: Ctx<()>| {
+        cx.set_context(Theme("light")).unwrap();
+
+        div(cx)
+          .child(themed)
+          .child(|cx| {
+            div(cx)
+              .provide(Theme("dark"))
+              .provide(Locale("en"))
+              .child(themed)
+              .child(|cx| div(cx).provide(Theme("dim")).child(themed))
+          })
+          .child(themed)
+      },
+    );
+
+    assert_eq!(
+      app.render_to_string(),
+      "<div><p theme=\"light\">none</p><div><p \
+       theme=\"dark\">en</p><div><p theme=\"dim\">en</p></div></div><p \
+       theme=\"light\">none</p></div>"
+    );
+  }
+
+  #[test]
+  fn updated_contexts_rerender_views() {
+    use futures::StreamExt;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Debug, PartialEq)]
+    struct Locale(&'static str);
+
+    let cxs = Rc::new(RefCell::new(vec![]));
+
+    let app = TestApp::new(App::new(
+      || (),
+      |_, _| (),
+      clone!([cxs], move |_, cx: Ctx<()>| {
+        cx.set_context(Locale("en")).unwrap();
+
+        cxs.borrow_mut().push(cx.clone());
+
+        let greeting = |cx: Ctx<()>| {
+          let locale = cx.context_stream::<Locale>().unwrap();
+
+          p(cx).dyn_text(locale.map(|locale| locale.0))
+        };
+
+        div(cx).child(greeting).child(clone!([cxs], move |cx| {
+          let cx = cx.provide(Locale("fr"));
+
+          cxs.borrow_mut().push(cx.clone());
+
+          greeting(cx)
+        }))
+      }),
+    ));
+
+    let (root, shadowed) = {
+      let cxs = cxs.borrow();
+
+      (cxs[0].clone(), cxs[1].clone())
+    };
+
+    root.update_context(Locale("de")).unwrap();
+
+    assert_eq!(*root.get_context::<Locale>().unwrap(), Locale("de"));
+    assert_eq!(*shadowed.get_context::<Locale>().unwrap(), Locale("fr"));
+    assert_eq!(
+      app.render_to_string(),
+      "<div><p><!-- <DynChild> -->de<!-- </DynChild> --></p><p><!-- \
+       <DynChild> -->fr<!-- </DynChild> --></p></div>"
+    );
+
+    assert!(matches!(
+      root.update_context(42),
+      Err(ContextError::NotSet { data: 42 })
+    ));
+  }
+
+  #[test]
+  fn async_updates_finish_before_diffing() {
+    use futures::channel::oneshot;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Clone, DiffableModel)]
+    struct Model {
+      results: String,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Search(&'static str),
+      Clear,
+    }
+
+    let replies = Rc::new(RefCell::new(vec![]));
+
+    let mut app = TestApp::new(
+      App::new_async(
+        || Model {
+          results: String::new(),
+        },
+        clone!([replies], move |model: Model, msg| {
+          let (tx, rx) = oneshot::channel::<()>();
+
+          replies.borrow_mut().push(Some(tx));
+
+          async move {
+            let _ = rx.await;
+
+            match msg {
+              Msg::Search(query) => Model {
+                results: format!("{}{query}", model.results),
+              },
+              Msg::Clear => Model {
+                results: String::new(),
+              },
+            }
+          }
+        }),
+        |vm: &ModelViewModel, cx| p(cx).dyn_text(&vm.results),
+      )
+      .pending_policy(|pending, new| match (pending, new) {
+        (Msg::Search(_), Msg::Search(_)) => PendingPolicy::Cancel,
+        _ => PendingPolicy::Queue,
+      }),
+    );
+
+    let reply = |i: usize| {
+      let _ = replies.borrow_mut()[i].take().unwrap().send(());
+    };
+
+    app.dispatch(Msg::Search("a"));
+    app.dispatch(Msg::Search("b"));
+    app.dispatch(Msg::Clear);
+    app.run_until_idle();
+
+    // "a" was cancelled by "b", which "Clear" is waiting for
+    assert_eq!(app.messages(), [Msg::Search("a"), Msg::Search("b")]);
+    assert_eq!(app.model().results, "");
+
+    reply(1);
+    app.run_until_idle();
+
+    assert_eq!(app.model().results, "b");
+    assert_eq!(
+      app.render_to_string(),
+      "<p><!-- <DynChild> -->b<!-- </DynChild> --></p>"
+    );
+
+    reply(2);
+    app.dispatch(Msg::Search("c"));
+    app.run_until_idle();
+
+    assert_eq!(app.model().results, "");
+    assert_eq!(
+      app.messages(),
+      [
+        Msg::Search("a"),
+        Msg::Search("b"),
+        Msg::Clear,
+        Msg::Search("c")
+      ]
+    );
+  }
+
+  #[test]
+  fn stop_handles_end_the_run_loop() {
+    use futures::StreamExt;
+    use std::{cell::RefCell, sync::mpsc, thread};
+
+    #[derive(Clone, Debug)]
+    enum Msg {
+      Tick,
+    }
+
+    let (started_tx, started_rx) = mpsc::channel();
+    let (tick_tx, tick_rx) = futures::channel::mpsc::unbounded::<()>();
+    let tick_rx = RefCell::new(Some(tick_rx));
+
+    let mut app = App::new(|| (), |_, Msg::Tick| (), |_, cx: Ctx<Msg>| div(cx))
+      .subscriptions(move |_| {
+        let tick_rx = tick_rx.borrow_mut().take();
+        let started_tx = started_tx.clone();
+
+        Sub::stream("ticks", move || {
+          started_tx.send(()).unwrap();
+
+          tick_rx.unwrap().map(|()| Msg::Tick)
+        })
+      });
+
+    let stop_handle = app.stop_handle();
+
+    // Stopped from another thread once the loop is up and waiting
+    let stopper = thread::spawn(move || {
+      started_rx.recv().unwrap();
+      stop_handle.stop();
+    });
+
+    futures::executor::block_on(app.run_until_stopped());
+
+    stopper.join().unwrap();
+
+    // Subscriptions are cancelled along with the loop
+    assert!(tick_tx.is_closed());
   }
 }
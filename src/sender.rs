@@ -0,0 +1,199 @@
+//! Sending messages into a running app from outside of its view.
+
+use futures::{
+  channel::mpsc::{Sender, UnboundedSender},
+  future,
+};
+use std::cell::RefCell;
+
+/// An error returned when a message could not be sent to an app.
+#[derive(Debug, derive_more::Display)]
+pub enum SendError<Msg> {
+  /// The queue of a bounded [`AppSender`] is full.
+  #[display(fmt = "the message queue is full")]
+  Full(Msg),
+  /// The app was shut down, so it will never process the message.
+  #[display(fmt = "the app was shut down")]
+  ShutDown(Msg),
+}
+
+impl<Msg> SendError<Msg> {
+  /// Returns `true` if the app was shut down.
+  pub fn is_shut_down(&self) -> bool {
+    matches!(self, Self::ShutDown(_))
+  }
+
+  /// Gets back the message which could not be sent.
+  pub fn into_inner(self) -> Msg {
+    match self {
+      Self::Full(msg) | Self::ShutDown(msg) => msg,
+    }
+  }
+}
+
+/// Sends messages into a running [`App`](crate::App) from outside of
+/// its view, such as from JS callbacks, websocket tasks or other apps.
+///
+/// Bounded senders, created with `App::bounded_sender`, only let a
+/// limited number of messages queue up before the runtime gets to
+/// them. As with [`futures::channel::mpsc::channel`], each clone can
+/// queue one more message on top of that.
+pub struct AppSender<Msg> {
+  msg_sender: UnboundedSender<Msg>,
+  bounded: Option<RefCell<Sender<Msg>>>,
+}
+
+impl<Msg> Clone for AppSender<Msg> {
+  fn clone(&self) -> Self {
+    Self {
+      msg_sender: self.msg_sender.clone(),
+      bounded: self
+        .bounded
+        .as_ref()
+        .map(|bounded| RefCell::new(bounded.borrow().clone())),
+    }
+  }
+}
+
+impl<Msg> AppSender<Msg> {
+  pub(crate) fn new(
+    msg_sender: UnboundedSender<Msg>,
+    bounded: Option<Sender<Msg>>,
+  ) -> Self {
+    Self {
+      msg_sender,
+      bounded: bounded.map(RefCell::new),
+    }
+  }
+
+  /// Returns `true` if the app was shut down, after which messages
+  /// can no longer be sent.
+  pub fn is_shut_down(&self) -> bool {
+    self.msg_sender.is_closed()
+  }
+
+  /// Sends `msg` without waiting, failing if the app was shut down
+  /// or, when bounded, if its queue is full.
+  pub fn try_send(&self, msg: Msg) -> Result<(), SendError<Msg>> {
+    if self.is_shut_down() {
+      return Err(SendError::ShutDown(msg));
+    }
+
+    match &self.bounded {
+      Some(bounded) => bounded.borrow_mut().try_send(msg).map_err(|err| {
+        if err.is_full() {
+          SendError::Full(err.into_inner())
+        } else {
+          SendError::ShutDown(err.into_inner())
+        }
+      }),
+      None => self
+        .msg_sender
+        .unbounded_send(msg)
+        .map_err(|err| SendError::ShutDown(err.into_inner())),
+    }
+  }
+
+  /// Sends `msg`, waiting for room in the queue when bounded.
+  ///
+  /// Fails only if the app was shut down.
+  pub async fn send(&self, msg: Msg) -> Result<(), SendError<Msg>> {
+    if let Some(bounded) = &self.bounded {
+      let ready =
+        future::poll_fn(|cx| bounded.borrow_mut().poll_ready(cx)).await;
+
+      if ready.is_err() {
+        return Err(SendError::ShutDown(msg));
+      }
+    }
+
+    self.try_send(msg)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+
+  #[test]
+  fn senders_inject_messages_from_outside() {
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Ping(u8),
+    }
+
+    let mut app = App::new(|| (), |_, _| (), |_, cx: Ctx<Msg>| div(cx));
+
+    let sender = app.sender();
+    let bounded = app.bounded_sender(1);
+    let stop_handle = app.stop_handle();
+
+    let mut app = TestApp::new(app);
+
+    sender.try_send(Msg::Ping(0)).unwrap();
+    bounded.try_send(Msg::Ping(1)).unwrap();
+    // Each sender can queue one message on top of the capacity
+    bounded.try_send(Msg::Ping(2)).unwrap();
+
+    assert!(matches!(
+      bounded.try_send(Msg::Ping(3)),
+      Err(SendError::Full(Msg::Ping(3)))
+    ));
+
+    app.run_until_idle();
+
+    bounded.try_send(Msg::Ping(3)).unwrap();
+
+    app.run_until_idle();
+
+    assert_eq!(
+      app.messages(),
+      [Msg::Ping(0), Msg::Ping(1), Msg::Ping(2), Msg::Ping(3)]
+    );
+
+    stop_handle.stop();
+
+    assert!(sender.is_shut_down());
+    assert!(bounded.try_send(Msg::Ping(4)).unwrap_err().is_shut_down());
+  }
+
+  #[test]
+  fn bounded_senders_wait_while_the_app_is_busy() {
+    use futures::FutureExt;
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Ping(u8),
+    }
+
+    let mut app = App::new(|| (), |_, _| (), |_, cx: Ctx<Msg>| div(cx));
+
+    let sender = app.sender();
+    let bounded = app.bounded_sender(1);
+
+    let mut app = TestApp::new(app);
+
+    bounded.try_send(Msg::Ping(1)).unwrap();
+    bounded.try_send(Msg::Ping(2)).unwrap();
+
+    let mut send = Box::pin(bounded.send(Msg::Ping(3)));
+
+    assert!(send.as_mut().now_or_never().is_none());
+
+    // Other messages are processed first, leaving the queue full
+    sender.try_send(Msg::Ping(0)).unwrap();
+
+    assert!(app.step());
+    assert!(send.as_mut().now_or_never().is_none());
+
+    assert!(app.step());
+    assert!(send.as_mut().now_or_never().unwrap().is_ok());
+
+    app.run_until_idle();
+
+    assert_eq!(
+      app.messages(),
+      [Msg::Ping(0), Msg::Ping(1), Msg::Ping(2), Msg::Ping(3)]
+    );
+  }
+}
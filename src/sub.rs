@@ -0,0 +1,266 @@
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+use crate::runtime::IntoMsg;
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+use futures::future;
+use futures::{stream::LocalBoxStream, Stream, StreamExt};
+use std::{
+  any::{self, Any, TypeId},
+  fmt,
+  hash::{Hash, Hasher},
+  rc::Rc,
+  time::Duration,
+};
+
+type StreamFn<Msg> = Box<dyn FnOnce() -> LocalBoxStream<'static, Msg>>;
+
+/// A subscription key with its type erased.
+trait AnyKey {
+  fn as_any(&self) -> &dyn Any;
+
+  fn eq_key(&self, other: &dyn AnyKey) -> bool;
+
+  fn hash_key(&self, state: &mut dyn Hasher);
+}
+
+impl<K: Hash + Eq + 'static> AnyKey for K {
+  fn as_any(&self) -> &dyn Any {
+    self
+  }
+
+  fn eq_key(&self, other: &dyn AnyKey) -> bool {
+    other.as_any().downcast_ref::<K>() == Some(self)
+  }
+
+  fn hash_key(&self, mut state: &mut dyn Hasher) {
+    TypeId::of::<K>().hash(&mut state);
+    self.hash(&mut state);
+  }
+}
+
+/// Identifies a subscription across calls to the subscriptions
+/// function, so the runtime can tell which subscriptions are new
+/// and which have gone away.
+///
+/// The key itself is kept, so keys whose hashes collide still tell
+/// their subscriptions apart.
+pub struct SubId {
+  key: Box<dyn AnyKey>,
+  type_name: &'static str,
+}
+
+impl SubId {
+  pub fn new<K: Hash + Eq + 'static>(key: K) -> Self {
+    Self {
+      key: Box::new(key),
+      type_name: any::type_name::<K>(),
+    }
+  }
+}
+
+impl PartialEq for SubId {
+  fn eq(&self, other: &Self) -> bool {
+    self.key.eq_key(&*other.key)
+  }
+}
+
+impl Eq for SubId {}
+
+impl Hash for SubId {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.key.hash_key(state);
+  }
+}
+
+impl fmt::Debug for SubId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("SubId").field(&self.type_name).finish()
+  }
+}
+
+/// Long-lived sources of messages, such as intervals or window events,
+/// which depend on the current model.
+///
+/// Subscriptions are identified by a key. As long as a subscription
+/// with the same key keeps being returned from the subscriptions
+/// function, the runtime keeps the original one running. Once it is
+/// no longer returned, it is cancelled.
+pub struct Sub<Msg>(pub(crate) Vec<(SubId, StreamFn<Msg>)>);
+
+impl<Msg> Default for Sub<Msg> {
+  fn default() -> Self {
+    Self::none()
+  }
+}
+
+impl<Msg> Sub<Msg> {
+  /// No subscriptions.
+  pub fn none() -> Self {
+    Self(vec![])
+  }
+
+  /// Combines many subscriptions into one.
+  pub fn batch(subs: impl IntoIterator<Item = Sub<Msg>>) -> Self {
+    Self(subs.into_iter().flat_map(|sub| sub.0).collect())
+  }
+}
+
+impl<Msg: 'static> Sub<Msg> {
+  /// Subscribes to the [`Stream`] returned by `f`.
+  ///
+  /// `f` is only called when a subscription with this `key` is not
+  /// already running.
+  pub fn stream<K, F, S>(key: K, f: F) -> Self
+  where
+    K: Hash + Eq + 'static,
+    F: FnOnce() -> S + 'static,
+    S: Stream<Item = Msg> + 'static,
+  {
+    Self(vec![(SubId::new(key), Box::new(|| f().boxed_local()))])
+  }
+
+  /// Calls `f` every `period`, sending the resulting message.
+  pub fn interval<K, F>(key: K, period: Duration, mut f: F) -> Self
+  where
+    K: Hash + Eq + 'static,
+    F: FnMut() -> Msg + 'static,
+  {
+    Self::stream(key, move || interval_stream(period).map(move |_| f()))
+  }
+
+  /// Listens for `event` on the `window`.
+  #[cfg(all(target_arch = "wasm32", feature = "web"))]
+  pub fn window_event<K, F, IMsg>(
+    key: K,
+    event: impl ToString + 'static,
+    mut f: F,
+  ) -> Self
+  where
+    K: Hash + Eq + 'static,
+    F: FnMut(&web_sys::Event) -> IMsg + 'static,
+    IMsg: IntoMsg<Msg>,
+  {
+    Self::stream(key, move || {
+      let (tx, rx) = futures::channel::mpsc::unbounded();
+
+      let listener = gloo::events::EventListener::new(
+        &gloo::utils::window(),
+        event.to_string(),
+        move |e| {
+          let _ = tx.unbounded_send(f(e).into_msg());
+        },
+      );
+
+      // The listener must live for as long as the stream does
+      rx.filter_map(move |msg: Option<Msg>| {
+        let _ = &listener;
+
+        future::ready(msg)
+      })
+    })
+  }
+
+  /// Transforms the messages produced by this subscription, useful for
+  /// embedding the subscriptions of a nested module.
+  pub fn map<NewMsg: 'static>(
+    self,
+    f: impl Fn(Msg) -> NewMsg + 'static,
+  ) -> Sub<NewMsg> {
+    let f = Rc::new(f);
+
+    Sub(
+      self
+        .0
+        .into_iter()
+        .map(|(id, stream_fn)| {
+          let stream_fn: StreamFn<NewMsg> = Box::new(clone!([f], move || {
+            stream_fn().map(move |msg| f(msg)).boxed_local()
+          }));
+
+          (id, stream_fn)
+        })
+        .collect(),
+    )
+  }
+}
+
+/// Ticks every `period` on the web, or from within a `tokio` runtime.
+///
+/// Elsewhere, there is no timer to drive it, so it never ticks and a
+/// warning is logged instead.
+fn interval_stream(period: Duration) -> LocalBoxStream<'static, ()> {
+  cfg_if::cfg_if! {
+    if #[cfg(all(target_arch = "wasm32", feature = "web"))] {
+      gloo::timers::future::IntervalStream::new(period.as_millis() as u32)
+        .boxed_local()
+    } else {
+      #[cfg(feature = "tokio")]
+      if tokio::runtime::Handle::try_current().is_ok() {
+        let mut interval = tokio::time::interval_at(
+          tokio::time::Instant::now() + period,
+          period,
+        );
+
+        return futures::stream::poll_fn(move |cx| {
+          interval.poll_tick(cx).map(|_| Some(()))
+        })
+        .boxed_local();
+      }
+
+      tracing::warn!(
+        ?period,
+        "`Sub::interval` needs a `tokio` runtime outside of the web, so \
+         it never ticks"
+      );
+
+      futures::stream::pending().boxed_local()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+
+  #[test]
+  fn subscriptions_with_colliding_key_hashes_both_run() {
+    use std::hash::{Hash, Hasher};
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Tick(u8),
+    }
+
+    #[derive(DiffableModel)]
+    struct Model {
+      ticks: usize,
+    }
+
+    #[derive(PartialEq, Eq)]
+    struct Key(u8);
+
+    impl Hash for Key {
+      fn hash<H: Hasher>(&self, _: &mut H) {}
+    }
+
+    let tick = |n| {
+      Sub::stream(Key(n), move || {
+        futures::stream::once(async move { Msg::Tick(n) })
+      })
+    };
+
+    let mut app = TestApp::new(
+      App::new(
+        || Model { ticks: 0 },
+        |model, Msg::Tick(_)| Model {
+          ticks: model.ticks + 1,
+        },
+        |vm: &ModelViewModel, cx| p(cx).dyn_text(&vm.ticks),
+      )
+      .subscriptions(move |_| Sub::batch([tick(1), tick(2)])),
+    );
+
+    app.run_until_idle();
+
+    assert_eq!(app.messages(), [Msg::Tick(1), Msg::Tick(2)]);
+  }
+}
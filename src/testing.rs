@@ -0,0 +1,216 @@
+//! Utilities for testing apps headlessly, without a browser or an
+//! async runtime.
+
+#[cfg(test)]
+pub(crate) mod fixtures;
+
+#[cfg(feature = "recording")]
+use crate::recording::Session;
+use crate::{
+  runtime::{DiffableModel, IntoUpdate},
+  App,
+};
+
+/// Drives an [`App`] synchronously, recording every message it
+/// processes.
+///
+/// ```no_run
+/// # use frappe_tea::{prelude::*, testing::TestApp};
+/// # #[derive(DiffableModel)]
+/// # struct Model {
+/// #   count: i32,
+/// # }
+/// # #[derive(Clone, Debug, PartialEq)]
+/// # enum Msg {
+/// #   Increment,
+/// # }
+/// # fn init() -> Model {
+/// #   Model { count: 0 }
+/// # }
+/// # fn update(model: Model, Msg::Increment: Msg) -> Model {
+/// #   Model { count: model.count + 1 }
+/// # }
+/// # fn view(_: &ModelViewModel, cx: Ctx<Msg>) -> impl IntoView<Msg> {
+/// #   div(cx)
+/// # }
+/// let mut app = TestApp::new(App::new(init, update, view));
+///
+/// app.dispatch(Msg::Increment);
+/// app.run_until_idle();
+///
+/// assert_eq!(app.model().count, 1);
+/// assert_eq!(app.messages(), [Msg::Increment]);
+/// ```
+pub struct TestApp<M: DiffableModel, Msg, UF> {
+  app: App<M, Msg, UF>,
+  /// Every message which was processed, in order.
+  log: Vec<Msg>,
+}
+
+impl<M, Msg, UF, U> TestApp<M, Msg, UF>
+where
+  M: DiffableModel,
+  Msg: Clone + 'static,
+  UF: FnMut(M, Msg) -> U,
+  U: IntoUpdate<M, Msg>,
+{
+  pub fn new(mut app: App<M, Msg, UF>) -> Self {
+    app.rt.start();
+
+    Self { app, log: vec![] }
+  }
+
+  /// Replays a recorded `session` on the app returned by `app_fn`,
+  /// which is given the recorded initial model.
+  ///
+  /// Effects and subscriptions are not run, as the messages they sent
+  /// were recorded along with the others.
+  ///
+  /// ```no_run
+  /// # use frappe_tea::{prelude::*, testing::TestApp};
+  /// # #[derive(DiffableModel, serde::Serialize, serde::Deserialize)]
+  /// # struct Model {
+  /// #   count: i32,
+  /// # }
+  /// # #[derive(Clone, serde::Serialize, serde::Deserialize)]
+  /// # enum Msg {
+  /// #   Increment,
+  /// # }
+  /// # fn update(model: Model, Msg::Increment: Msg) -> Model {
+  /// #   Model { count: model.count + 1 }
+  /// # }
+  /// # fn view(_: &ModelViewModel, cx: Ctx<Msg>) -> impl IntoView<Msg> {
+  /// #   div(cx)
+  /// # }
+  /// # type Report = error_stack::Report<SessionError>;
+  /// # fn replay(expected: &str) -> Result<(), Report> {
+  /// let session = Session::read_from("bug.json", SessionFormat::Json)?;
+  ///
+  /// let app = TestApp::replay(
+  ///   |init| App::new(move || init, update, view),
+  ///   session,
+  /// );
+  ///
+  /// assert_eq!(app.render_to_string(), expected);
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[cfg(feature = "recording")]
+  pub fn replay(
+    app_fn: impl FnOnce(M) -> App<M, Msg, UF>,
+    session: Session<M, Msg>,
+  ) -> Self {
+    let (init_model, msgs) = session.into_parts();
+
+    let mut app = app_fn(init_model);
+
+    app.rt.set_replaying();
+
+    let mut this = Self::new(app);
+
+    for msg in msgs {
+      this.dispatch(msg);
+      this.run_until_idle();
+    }
+
+    this
+  }
+
+  /// Queues a message, as if it had been sent from the view.
+  ///
+  /// The message is not processed until [`TestApp::step`] or
+  /// [`TestApp::run_until_idle`] is called.
+  pub fn dispatch(&mut self, msg: Msg) {
+    self
+      .app
+      .rt
+      .msg_sender()
+      .unbounded_send(msg)
+      .expect("app to not be stopped");
+  }
+
+  /// Processes the next queued message, returning `false` if there
+  /// wasn't one.
+  pub fn step(&mut self) -> bool {
+    #[cfg(feature = "time-travel")]
+    self.app.rt.apply_time_travel_controls();
+
+    if let Some(msg) = self.app.rt.try_next_msg() {
+      self.log.push(msg.clone());
+
+      self.app.rt.step(msg);
+
+      self.drive_tasks();
+
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Processes messages, and the effects, subscriptions and dynamic
+  /// views they trigger, until no more progress can be made without
+  /// waiting.
+  ///
+  /// When batching, a frame is assumed to happen each time the queue
+  /// runs dry. Debounced saves of persisted models are also written
+  /// then.
+  ///
+  /// Returns the number of messages which were processed.
+  pub fn run_until_idle(&mut self) -> usize {
+    let mut processed = 0;
+
+    loop {
+      self.drive_tasks();
+
+      if !self.step() {
+        self.app.rt.flush();
+
+        #[cfg(feature = "persistence")]
+        self.app.rt.save_pending();
+
+        self.drive_tasks();
+
+        if !self.step() {
+          break;
+        }
+      }
+
+      processed += 1;
+    }
+
+    processed
+  }
+
+  /// The current model.
+  pub fn model(&self) -> &M {
+    self.app.rt.model()
+  }
+
+  /// The current view model.
+  pub fn view_model(&self) -> &M::ViewModel {
+    self.app.rt.view_model()
+  }
+
+  /// Every message which was processed so far, in order.
+  pub fn messages(&self) -> &[Msg] {
+    &self.log
+  }
+
+  /// Renders the current view to a [`String`].
+  #[cfg(feature = "ssr")]
+  pub fn render_to_string(&self) -> String {
+    self.app.render_to_string()
+  }
+
+  /// Gets the wrapped [`App`] back.
+  pub fn into_inner(self) -> App<M, Msg, UF> {
+    self.app
+  }
+
+  fn drive_tasks(&mut self) {
+    self.app.rt.poll_tasks();
+
+    self.app.rt.poll_pending();
+  }
+}
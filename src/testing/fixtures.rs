@@ -0,0 +1,41 @@
+//! A counter app shared by the tests of each module.
+
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, DiffableModel, Serialize, Deserialize)]
+pub(crate) struct Counter {
+  pub(crate) count: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) enum CounterMsg {
+  Add(i32),
+}
+
+pub(crate) type CounterApp =
+  App<Counter, CounterMsg, fn(Counter, CounterMsg) -> Counter>;
+
+/// A counter starting at `count`.
+pub(crate) fn counter(count: i32) -> CounterApp {
+  App::new(move || Counter { count }, update as _, view)
+}
+
+pub(crate) fn update(
+  Counter { count }: Counter,
+  CounterMsg::Add(n): CounterMsg,
+) -> Counter {
+  Counter { count: count + n }
+}
+
+pub(crate) fn view(
+  vm: &CounterViewModel,
+  cx: Ctx<CounterMsg>,
+) -> impl IntoView<CounterMsg> {
+  p(cx).dyn_text(&vm.count)
+}
+
+/// What [`view`] renders to once the count is `count`.
+pub(crate) fn rendered(count: i32) -> String {
+  format!("<p><!-- <DynChild> -->{count}<!-- </DynChild> --></p>")
+}
@@ -0,0 +1,461 @@
+//! Recording of processed messages, so that an app can be rewound to,
+//! and replayed from, any past step.
+
+use futures::{
+  channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+  StreamExt,
+};
+use std::{
+  cell::RefCell,
+  collections::{BTreeMap, BTreeSet},
+  rc::Rc,
+  task::{self, Poll},
+};
+
+/// How many steps apart model snapshots are taken.
+///
+/// Jumping to a step replays at most this many messages.
+const SNAPSHOT_INTERVAL: usize = 32;
+
+/// The recorded messages, shared between the runtime and its handles.
+struct Timeline<Msg> {
+  msgs: Vec<Msg>,
+  /// How many of the recorded messages are applied to the current
+  /// model.
+  cursor: usize,
+  /// The indices of the messages which are left out when replaying.
+  skipped: BTreeSet<usize>,
+  paused: bool,
+}
+
+pub(crate) enum Control {
+  JumpTo(usize),
+  StepBy(isize),
+  Toggle(usize),
+  Pause,
+  Resume,
+}
+
+/// The time-travel state kept by the runtime.
+pub(crate) struct History<M, Msg> {
+  timeline: Rc<RefCell<Timeline<Msg>>>,
+  /// Copies of the model after the given number of steps, oldest
+  /// first.
+  snapshots: Vec<(usize, M)>,
+  /// The models which async updates resolved to, by the index of
+  /// their message, as those updates can't be replayed synchronously.
+  resolved: BTreeMap<usize, M>,
+  control_sender: UnboundedSender<Control>,
+  control_receiver: UnboundedReceiver<Control>,
+  clone_model: fn(&M) -> M,
+  clone_msg: fn(&Msg) -> Msg,
+}
+
+impl<M, Msg> History<M, Msg> {
+  pub fn new(model: &M) -> Self
+  where
+    M: Clone,
+    Msg: Clone,
+  {
+    let (control_sender, control_receiver) = mpsc::unbounded();
+
+    Self {
+      timeline: Rc::new(RefCell::new(Timeline {
+        msgs: vec![],
+        cursor: 0,
+        skipped: Default::default(),
+        paused: false,
+      })),
+      snapshots: vec![(0, model.clone())],
+      resolved: Default::default(),
+      control_sender,
+      control_receiver,
+      clone_model: M::clone,
+      clone_msg: Msg::clone,
+    }
+  }
+
+  pub fn handle(&self) -> TimeTravelHandle<Msg> {
+    TimeTravelHandle {
+      timeline: self.timeline.clone(),
+      control: self.control_sender.clone(),
+    }
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.timeline.borrow().paused
+  }
+
+  pub fn set_paused(&self, paused: bool) {
+    self.timeline.borrow_mut().paused = paused;
+  }
+
+  pub fn current_step(&self) -> usize {
+    self.timeline.borrow().cursor
+  }
+
+  pub fn poll_control(&mut self, cx: &mut task::Context) -> Poll<Control> {
+    // We hold a sender ourselves, so the stream never ends
+    self
+      .control_receiver
+      .poll_next_unpin(cx)
+      .map(Option::unwrap)
+  }
+
+  #[cfg(not(all(target_arch = "wasm32", feature = "web")))]
+  pub fn try_next_control(&mut self) -> Option<Control> {
+    self.control_receiver.try_next().ok().flatten()
+  }
+
+  /// Records `msg` as the step following the current one, discarding
+  /// any steps which came after it.
+  pub fn record(&mut self, msg: &Msg) {
+    let mut timeline = self.timeline.borrow_mut();
+
+    let cursor = timeline.cursor;
+
+    timeline.msgs.truncate(cursor);
+    timeline.skipped.retain(|index| *index < cursor);
+    timeline.msgs.push((self.clone_msg)(msg));
+    timeline.cursor += 1;
+
+    self.snapshots.retain(|(step, _)| *step <= cursor);
+    self.resolved.retain(|index, _| *index < cursor);
+  }
+
+  /// Forgets the last recorded message, such as when its update was
+  /// cancelled before it finished.
+  pub fn forget_last(&mut self) {
+    let mut timeline = self.timeline.borrow_mut();
+
+    if timeline.msgs.pop().is_some() {
+      timeline.cursor = timeline.msgs.len();
+    }
+
+    let len = timeline.msgs.len();
+
+    timeline.skipped.retain(|index| *index < len);
+
+    let cursor = timeline.cursor;
+
+    self.snapshots.retain(|(step, _)| *step <= cursor);
+    self.resolved.retain(|index, _| *index < len);
+  }
+
+  /// Keeps `model`, which the async update of the last recorded
+  /// message resolved to, for replaying it.
+  pub fn resolved(&mut self, model: &M) {
+    let Some(index) = self.timeline.borrow().msgs.len().checked_sub(1) else {
+      return;
+    };
+
+    self.resolved.insert(index, (self.clone_model)(model));
+  }
+
+  /// Toggles whether the message at `index` is skipped, discarding
+  /// the snapshots which included it.
+  pub fn toggle(&mut self, index: usize) {
+    let mut timeline = self.timeline.borrow_mut();
+
+    if index >= timeline.msgs.len() {
+      return;
+    }
+
+    if !timeline.skipped.remove(&index) {
+      timeline.skipped.insert(index);
+    }
+
+    self.snapshots.retain(|(step, _)| *step <= index);
+  }
+
+  /// Takes a snapshot of `model`, which is the result of the last
+  /// recorded message, if one is due.
+  pub fn snapshot(&mut self, model: &M) {
+    let cursor = self.current_step();
+
+    if cursor.is_multiple_of(SNAPSHOT_INTERVAL) {
+      self.snapshots.push((cursor, (self.clone_model)(model)));
+    }
+  }
+
+  /// Rebuilds the model after `step` messages, by replaying the
+  /// recorded messages since the closest snapshot through `update`,
+  /// leaving out the skipped ones.
+  ///
+  /// `update` is also given the model the message resolved to, if its
+  /// update was async.
+  pub fn rebuild(
+    &self,
+    step: usize,
+    mut update: impl FnMut(M, Msg, Option<M>) -> M,
+  ) -> M {
+    let msgs = self.replayed_msgs(step);
+
+    self.timeline.borrow_mut().cursor = msgs.len();
+
+    let (start, model) = self.closest_snapshot(msgs.len());
+
+    msgs
+      .into_iter()
+      .skip(start)
+      .flatten()
+      .fold((self.clone_model)(model), |model, (msg, resolved)| {
+        update(model, msg, resolved)
+      })
+  }
+
+  /// Replays every recorded message like [`History::rebuild`], but
+  /// without moving the current step, returning the model after each
+  /// step from `first` on.
+  pub fn replay_from(
+    &self,
+    first: usize,
+    mut update: impl FnMut(M, Msg, Option<M>) -> M,
+  ) -> Vec<M> {
+    let msgs = self.replayed_msgs(usize::MAX);
+
+    let (start, model) =
+      self.closest_snapshot(first.saturating_sub(1).min(msgs.len()));
+
+    let mut model = (self.clone_model)(model);
+    let mut models = vec![];
+
+    for (index, msg) in msgs.into_iter().enumerate().skip(start) {
+      if let Some((msg, resolved)) = msg {
+        model = update(model, msg, resolved);
+      }
+
+      // Skipped steps keep the model of the step before them
+      if index + 1 >= first {
+        models.push((self.clone_model)(&model));
+      }
+    }
+
+    models
+  }
+
+  /// The recorded messages up to `step`, along with the models their
+  /// async updates resolved to, or `None` for the skipped ones.
+  fn replayed_msgs(&self, step: usize) -> Vec<Option<(Msg, Option<M>)>> {
+    let timeline = self.timeline.borrow();
+
+    let step = step.min(timeline.msgs.len());
+
+    timeline.msgs[..step]
+      .iter()
+      .enumerate()
+      .map(|(index, msg)| {
+        (!timeline.skipped.contains(&index)).then(|| {
+          let resolved = self.resolved.get(&index).map(self.clone_model);
+
+          ((self.clone_msg)(msg), resolved)
+        })
+      })
+      .collect()
+  }
+
+  /// The latest snapshot taken at or before `step`.
+  fn closest_snapshot(&self, step: usize) -> (usize, &M) {
+    let (start, model) = self
+      .snapshots
+      .iter()
+      .rev()
+      .find(|(snapshot_step, _)| *snapshot_step <= step)
+      .expect("the initial model to always be snapshotted");
+
+    (*start, model)
+  }
+}
+
+/// A handle for inspecting and controlling the message history of an
+/// app with time-travel enabled.
+///
+/// Commands take effect the next time the runtime processes events,
+/// and the view model is re-diffed to reflect the selected step.
+#[derive(educe::Educe)]
+#[educe(Clone)]
+pub struct TimeTravelHandle<Msg> {
+  timeline: Rc<RefCell<Timeline<Msg>>>,
+  control: UnboundedSender<Control>,
+}
+
+impl<Msg: Clone> TimeTravelHandle<Msg> {
+  /// Every recorded message, oldest first.
+  pub fn messages(&self) -> Vec<Msg> {
+    self.timeline.borrow().msgs.clone()
+  }
+
+  /// The number of recorded steps.
+  pub fn len(&self) -> usize {
+    self.timeline.borrow().msgs.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// How many of the recorded messages are applied to the current
+  /// model.
+  pub fn current_step(&self) -> usize {
+    self.timeline.borrow().cursor
+  }
+
+  /// Returns `true` if the message at `index` is left out when
+  /// replaying.
+  pub fn is_skipped(&self, index: usize) -> bool {
+    self.timeline.borrow().skipped.contains(&index)
+  }
+
+  /// Returns `true` if live messages are currently being held back.
+  pub fn is_paused(&self) -> bool {
+    self.timeline.borrow().paused
+  }
+
+  /// Shows the model as it was after the first `step` messages.
+  ///
+  /// Messages are replayed without running their effects.
+  pub fn jump_to(&self, step: usize) {
+    self.send(Control::JumpTo(step));
+  }
+
+  /// Goes back a single step.
+  pub fn step_back(&self) {
+    self.send(Control::StepBy(-1));
+  }
+
+  /// Replays a single step.
+  pub fn step_forward(&self) {
+    self.send(Control::StepBy(1));
+  }
+
+  /// Replays every step up to the latest one.
+  pub fn replay(&self) {
+    self.send(Control::JumpTo(usize::MAX));
+  }
+
+  /// Leaves the message at `index` out of the model, or puts it back
+  /// in if it was already left out.
+  ///
+  /// The current step is replayed to reflect the change.
+  pub fn toggle(&self, index: usize) {
+    self.send(Control::Toggle(index));
+  }
+
+  /// Stops processing live messages, which stay queued until
+  /// [`TimeTravelHandle::resume`] is called.
+  pub fn pause(&self) {
+    self.send(Control::Pause);
+  }
+
+  /// Resumes processing live messages.
+  ///
+  /// They are applied on top of the current step, discarding any
+  /// steps which came after it.
+  pub fn resume(&self) {
+    self.send(Control::Resume);
+  }
+
+  fn send(&self, control: Control) {
+    let _ = self.control.unbounded_send(control);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    prelude::*,
+    testing::{
+      fixtures::{self, Counter, CounterMsg::Add},
+      TestApp,
+    },
+  };
+
+  #[test]
+  fn time_travel_rewinds_and_replays() {
+    let app = fixtures::counter(0).time_travel();
+
+    let history = app.time_travel_handle().unwrap();
+
+    let mut app = TestApp::new(app);
+
+    for n in 1..=3 {
+      app.dispatch(Add(n));
+    }
+
+    app.run_until_idle();
+
+    assert_eq!(history.messages(), [Add(1), Add(2), Add(3)]);
+
+    history.jump_to(1);
+    app.run_until_idle();
+
+    assert_eq!(app.model().count, 1);
+    assert_eq!(app.view_model().count.get(), 1);
+
+    history.step_forward();
+    history.pause();
+    app.dispatch(Add(10));
+    app.run_until_idle();
+
+    assert_eq!(app.model().count, 3);
+    assert_eq!(history.len(), 3);
+
+    // Live messages branch off of the selected step
+    history.resume();
+    app.run_until_idle();
+
+    assert_eq!(app.model().count, 13);
+    assert_eq!(history.messages(), [Add(1), Add(2), Add(10)]);
+    assert_eq!(app.render_to_string(), fixtures::rendered(13));
+  }
+
+  #[test]
+  fn time_travel_replays_resolved_async_updates() {
+    use futures::channel::oneshot;
+    use std::{cell::RefCell, rc::Rc};
+
+    let replies = Rc::new(RefCell::new(vec![]));
+
+    let app = App::new_async(
+      || Counter { count: 0 },
+      clone!([replies], move |Counter { count }, Add(n)| {
+        let (tx, rx) = oneshot::channel::<()>();
+
+        replies.borrow_mut().push(tx);
+
+        async move {
+          let _ = rx.await;
+
+          Counter { count: count + n }
+        }
+      }),
+      fixtures::view,
+    )
+    .time_travel();
+
+    let history = app.time_travel_handle().unwrap();
+
+    let mut app = TestApp::new(app);
+
+    for n in [1, 10] {
+      app.dispatch(Add(n));
+      app.run_until_idle();
+
+      let _ = replies.borrow_mut().pop().unwrap().send(());
+      app.run_until_idle();
+    }
+
+    assert_eq!(app.model().count, 11);
+
+    history.jump_to(1);
+    app.run_until_idle();
+
+    assert_eq!(app.model().count, 1);
+
+    history.jump_to(2);
+    app.run_until_idle();
+
+    assert_eq!(app.model().count, 11);
+    assert_eq!(app.render_to_string(), fixtures::rendered(11));
+  }
+}
@@ -1,6 +1,13 @@
-use futures::Future;
-
-use crate::prelude::Ctx;
+use futures::{
+  channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+  future::{self, AbortHandle, LocalBoxFuture},
+  stream::FuturesUnordered,
+  Future, FutureExt, StreamExt,
+};
+use std::{
+  task::{self, Poll},
+  time::Duration,
+};
 
 /// Helper macro for planning out new API designs.
 ///
@@ -26,26 +33,261 @@ macro_rules! debug {
   };
 }
 
-/// Spawns a `!Send` [`Future`].
-pub fn spawn_local(fut: impl Future<Output = ()> + 'static) {
+type Task = LocalBoxFuture<'static, ()>;
+
+/// Spawns `!Send` futures onto the [`Executor`] of an app, so they
+/// are driven by its runtime loop, whichever executor that runs on.
+#[derive(Clone)]
+pub(crate) struct Spawner(UnboundedSender<Task>);
+
+impl Spawner {
+  /// Spawns `fut`, which is dropped if the app already is.
+  pub fn spawn(&self, fut: impl Future<Output = ()> + 'static) {
+    let _ = self.0.unbounded_send(fut.boxed_local());
+  }
+
+  /// Spawns `fut`, which is cancelled when the returned [`TaskGuard`]
+  /// is dropped.
+  pub fn spawn_guarded(
+    &self,
+    fut: impl Future<Output = ()> + 'static,
+  ) -> TaskGuard {
+    let (fut, handle) = future::abortable(fut);
+
+    self.spawn(fut.map(|_| ()));
+
+    TaskGuard(handle)
+  }
+}
+
+/// Drives the futures spawned by the views, effects and subscriptions
+/// of an app.
+pub(crate) struct Executor {
+  spawner: Spawner,
+  spawned: UnboundedReceiver<Task>,
+  tasks: FuturesUnordered<Task>,
+}
+
+impl Default for Executor {
+  fn default() -> Self {
+    let (tx, rx) = mpsc::unbounded();
+
+    Self {
+      spawner: Spawner(tx),
+      spawned: rx,
+      tasks: Default::default(),
+    }
+  }
+}
+
+impl Executor {
+  pub fn spawner(&self) -> Spawner {
+    self.spawner.clone()
+  }
+
+  /// Polls the tasks until none of them can make any more progress,
+  /// including the ones they spawn in turn.
+  pub fn poll(&mut self, cx: &mut task::Context) {
+    self.receive_spawned(cx);
+
+    loop {
+      while let Poll::Ready(Some(())) = self.tasks.poll_next_unpin(cx) {}
+
+      if !self.receive_spawned(cx) {
+        break;
+      }
+    }
+  }
+
+  /// Moves the newly spawned tasks into the set being polled,
+  /// returning `true` if there were any.
+  fn receive_spawned(&mut self, cx: &mut task::Context) -> bool {
+    let mut received = false;
+
+    while let Poll::Ready(Some(task)) = self.spawned.poll_next_unpin(cx) {
+      self.tasks.push(task);
+
+      received = true;
+    }
+
+    received
+  }
+
+  /// Polls the tasks without waiting, such as outside of the runtime
+  /// loop.
+  pub fn run_until_stalled(&mut self) {
+    self.poll(&mut task::Context::from_waker(
+      futures::task::noop_waker_ref(),
+    ));
+  }
+}
+
+/// Cancels the task it was created for when dropped.
+pub struct TaskGuard(AbortHandle);
+
+impl Drop for TaskGuard {
+  fn drop(&mut self) {
+    self.0.abort();
+  }
+}
+
+/// Resolves on the next animation frame on the web, or after `tick`
+/// elsewhere.
+///
+/// Outside of a `tokio` runtime, this resolves immediately.
+pub fn next_frame(tick: Duration) -> LocalBoxFuture<'static, ()> {
+  cfg_if::cfg_if! {
+    if #[cfg(all(target_arch = "wasm32", feature = "web"))] {
+      let _ = tick;
+
+      let (tx, rx) = futures::channel::oneshot::channel();
+
+      let handle = gloo::render::request_animation_frame(move |_| {
+        let _ = tx.send(());
+      });
+
+      async move {
+        let _ = rx.await;
+
+        drop(handle);
+      }
+      .boxed_local()
+    } else {
+      #[cfg(feature = "tokio")]
+      if tokio::runtime::Handle::try_current().is_ok() {
+        return tokio::time::sleep(tick).boxed_local();
+      }
+
+      let _ = tick;
+
+      future::ready(()).boxed_local()
+    }
+  }
+}
+
+/// Resolves after `duration`.
+///
+/// Outside of the web or a `tokio` runtime, this resolves immediately.
+#[cfg(feature = "persistence")]
+pub fn sleep(duration: Duration) -> LocalBoxFuture<'static, ()> {
   cfg_if::cfg_if! {
     if #[cfg(all(target_arch = "wasm32", feature = "web"))] {
-      wasm_bindgen_futures::spawn_local(fut);
-    } else if #[cfg(feature = "tokio")] {
-      let local = tokio::task::LocalSet::new();
+      gloo::timers::future::sleep(duration).boxed_local()
+    } else {
+      #[cfg(feature = "tokio")]
+      if tokio::runtime::Handle::try_current().is_ok() {
+        return tokio::time::sleep(duration).boxed_local();
+      }
+
+      let _ = duration;
+
+      future::ready(()).boxed_local()
+    }
+  }
+}
+
+/// How long idle periods last when the browser doesn't tell, or when
+/// not running on the web.
+const IDLE_BUDGET: Duration = Duration::from_millis(5);
 
-      local.spawn_local(fut);
+/// A period during which the event loop has nothing more urgent to
+/// do.
+pub struct IdlePeriod {
+  #[cfg(all(target_arch = "wasm32", feature = "web"))]
+  deadline: IdleDeadline,
+  #[cfg(not(all(target_arch = "wasm32", feature = "web")))]
+  end: std::time::Instant,
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+enum IdleDeadline {
+  Callback(web_sys::IdleDeadline),
+  /// The end of the period, in milliseconds since the epoch, for
+  /// browsers without `requestIdleCallback`.
+  Timeout(f64),
+}
+
+impl IdlePeriod {
+  /// Returns `true` while there is still time left in this period.
+  pub fn has_time_remaining(&self) -> bool {
+    cfg_if::cfg_if! {
+      if #[cfg(all(target_arch = "wasm32", feature = "web"))] {
+        match &self.deadline {
+          IdleDeadline::Callback(deadline) => deadline.time_remaining() > 0.0,
+          IdleDeadline::Timeout(end) => js_sys::Date::now() < *end,
+        }
+      } else {
+        std::time::Instant::now() < self.end
+      }
     }
   }
 }
 
-/// Spawns a `Send` [`Future`].
-pub fn spawn(fut: impl Future<Output = ()> + Send + 'static) {
+/// Resolves once the event loop is idle.
+///
+/// On the web, this is driven by `requestIdleCallback`, or by a
+/// timeout where it is not supported, such as in Safari. Elsewhere,
+/// this yields to the executor once.
+pub fn next_idle_period() -> LocalBoxFuture<'static, IdlePeriod> {
   cfg_if::cfg_if! {
     if #[cfg(all(target_arch = "wasm32", feature = "web"))] {
-      wasm_bindgen_futures::spawn_local(fut);
-    } else if #[cfg(feature = "tokio")] {
-      tokio::task::spawn(fut);
+      use wasm_bindgen::{closure::Closure, JsCast};
+
+      let window = gloo::utils::window();
+
+      let supported =
+        js_sys::Reflect::has(&window, &"requestIdleCallback".into())
+          .unwrap_or(false);
+
+      if !supported {
+        return async {
+          gloo::timers::future::sleep(Duration::ZERO).await;
+
+          IdlePeriod {
+            deadline: IdleDeadline::Timeout(
+              js_sys::Date::now() + IDLE_BUDGET.as_secs_f64() * 1000.0,
+            ),
+          }
+        }
+        .boxed_local();
+      }
+
+      let (tx, rx) = futures::channel::oneshot::channel();
+
+      let callback =
+        Closure::once_into_js(move |deadline: web_sys::IdleDeadline| {
+          let _ = tx.send(deadline);
+        });
+
+      window
+        .request_idle_callback(callback.unchecked_ref())
+        .expect("`requestIdleCallback` to be supported");
+
+      async move {
+        IdlePeriod {
+          deadline: IdleDeadline::Callback(
+            rx.await.expect("idle callback to be called"),
+          ),
+        }
+      }
+      .boxed_local()
+    } else {
+      let mut yielded = false;
+
+      future::poll_fn(move |cx| {
+        if yielded {
+          Poll::Ready(IdlePeriod {
+            end: std::time::Instant::now() + IDLE_BUDGET,
+          })
+        } else {
+          yielded = true;
+
+          cx.waker().wake_by_ref();
+
+          Poll::Pending
+        }
+      })
+      .boxed_local()
     }
   }
 }
@@ -1,6 +1,13 @@
-use crate::runtime::Ctx;
+use crate::{
+  runtime::{Ctx, MsgDispatcher},
+  utils::TaskGuard,
+};
 use error_stack::{report, Context};
-use std::{cell::RefCell, collections::HashMap, fmt, ops::Deref, rc::Rc};
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+use std::collections::HashMap;
+use std::{
+  any::Any, cell::RefCell, collections::BTreeMap, fmt, ops::Deref, rc::Rc,
+};
 #[cfg(all(target_arch = "wasm32", feature = "web"))]
 use wasm_bindgen::{JsCast, JsValue};
 
@@ -25,6 +32,56 @@ impl<Msg> IntoView<Msg> for View<Msg> {
   }
 }
 
+impl<Msg: 'static> View<Msg> {
+  /// Converts a view built for a nested module into a view for its
+  /// parent, passing every message through `f`.
+  ///
+  /// Views built with a [`Ctx`] from [`Ctx::map`] already send their
+  /// messages through that mapping, which is kept.
+  pub fn map_msg<ParentMsg: 'static>(
+    self,
+    f: impl Fn(Msg) -> ParentMsg + 'static,
+  ) -> View<ParentMsg> {
+    let Self(ViewInner { cx, kind }) = self;
+
+    let parent_cx = Ctx {
+      msg_dispatcher: MsgDispatcher::unbound(),
+      data: cx.data.clone(),
+      spawner: cx.spawner.clone(),
+    };
+
+    cx.msg_dispatcher.bind_with(&parent_cx.msg_dispatcher, f);
+
+    View(ViewInner {
+      cx: parent_cx,
+      kind: ViewKind::Mapped(Box::new(View(ViewInner { cx, kind }))),
+    })
+  }
+
+  /// Makes messages from this view reach `parent`, if they were not
+  /// already going elsewhere.
+  pub(crate) fn attach_to(&self, parent: &Ctx<Msg>) {
+    self.0.cx.msg_dispatcher.bind_to(&parent.msg_dispatcher);
+  }
+}
+
+/// A [`View`] whose message type was erased, as is the case for the
+/// views returned from [`View::map_msg`].
+pub(crate) trait AnyView: fmt::Display {
+  /// Gets the backing [`Node`].
+  ///
+  /// [Node]: web_sys::Node
+  #[cfg(all(target_arch = "wasm32", feature = "web"))]
+  fn get_node(&self) -> web_sys::Node;
+}
+
+impl<Msg> AnyView for View<Msg> {
+  #[cfg(all(target_arch = "wasm32", feature = "web"))]
+  fn get_node(&self) -> web_sys::Node {
+    self.0.kind.get_node()
+  }
+}
+
 /// The non-public struct for [`View`].
 #[derive(derive_more::Display)]
 #[display(fmt = "{kind}")]
@@ -43,6 +100,8 @@ pub(crate) enum ViewKind<Msg> {
   Text(Text),
   Comment(Comment),
   Component(Component<Msg>),
+  /// A view with a different message type.
+  Mapped(Box<dyn AnyView>),
 }
 
 impl<Msg> ViewKind<Msg> {
@@ -97,6 +156,7 @@ impl<Msg> ViewKind<Msg> {
       Self::Text(Text { node, .. }) => node.clone(),
       Self::Comment(Comment { node, .. }) => node.clone(),
       Self::Component(Component { fragment, .. }) => fragment.clone().into(),
+      Self::Mapped(view) => view.get_node(),
     }
   }
 
@@ -108,26 +168,47 @@ impl<Msg> ViewKind<Msg> {
     }
   }
 
-  pub fn set_attributes(&mut self, attrs: HashMap<String, String>) {
-    #[cfg(all(target_arch = "wasm32", feature = "web"))]
-    {
-      let node = self.get_node();
-
-      attrs.iter().for_each(|(n, v)| {
-        node
-          .unchecked_ref::<web_sys::Element>()
-          .set_attribute(n, v)
-          .expect("attribute to be valid");
-      });
+  pub fn set_attributes(&mut self, attrs: BTreeMap<String, String>) {
+    if let Some(setter) = self.attribute_setter() {
+      attrs.into_iter().for_each(|(n, v)| setter.set(&n, v));
     }
+  }
 
+  /// Keeps `task` running for as long as [`Html`] and [`VoidHtml`]
+  /// views are, cancelling it right away on others.
+  pub fn own_task(&mut self, task: TaskGuard) {
     match self {
-      Self::Html(Html { attributes, .. })
-      | Self::VoidHtml(VoidHtml { attributes, .. }) => *attributes = attrs,
+      Self::Html(Html { tasks, .. })
+      | Self::VoidHtml(VoidHtml { tasks, .. }) => tasks.push(task),
       _ => {}
     }
   }
 
+  /// Gets an [`AttributeSetter`] for [`Html`] and [`VoidHtml`] views,
+  /// which can be used to update attributes after the view was
+  /// created.
+  pub fn attribute_setter(&self) -> Option<AttributeSetter> {
+    match self {
+      Self::Html(Html {
+        attributes,
+        #[cfg(all(target_arch = "wasm32", feature = "web"))]
+        node,
+        ..
+      })
+      | Self::VoidHtml(VoidHtml {
+        attributes,
+        #[cfg(all(target_arch = "wasm32", feature = "web"))]
+        node,
+        ..
+      }) => Some(AttributeSetter {
+        attributes: attributes.clone(),
+        #[cfg(all(target_arch = "wasm32", feature = "web"))]
+        node: node.clone(),
+      }),
+      _ => None,
+    }
+  }
+
   /// Sets the event listeners for [`Html`] and [`VoidHtml`]
   /// views, does nothing on others.
   #[cfg(all(target_arch = "wasm32", feature = "web"))]
@@ -163,10 +244,36 @@ impl<Msg> Drop for ViewKind<Msg> {
       // No need to remove it from the DOM, as this will happen automatically
       // when its' containing `Comment`s are dropped
       Self::Component(_) | Self::Comment(Comment { .. }) => {}
+      // The wrapped view removes itself when dropped
+      Self::Mapped(_) => {}
     }
   }
 }
 
+type Attributes = Rc<RefCell<BTreeMap<String, String>>>;
+
+/// Sets the attributes of an [`Html`] or [`VoidHtml`] view, keeping
+/// the DOM and the rendered HTML in sync.
+#[derive(Clone)]
+pub(crate) struct AttributeSetter {
+  attributes: Attributes,
+  #[cfg(all(target_arch = "wasm32", feature = "web"))]
+  node: web_sys::Node,
+}
+
+impl AttributeSetter {
+  pub fn set(&self, name: &str, value: String) {
+    #[cfg(all(target_arch = "wasm32", feature = "web"))]
+    self
+      .node
+      .unchecked_ref::<web_sys::Element>()
+      .set_attribute(name, &value)
+      .expect("attribute to be valid");
+
+    self.attributes.borrow_mut().insert(name.to_owned(), value);
+  }
+}
+
 /// Represents and HTML element.
 pub(crate) struct Html<Msg> {
   /// Name of the HTML element, such as `div` or `a`.
@@ -177,13 +284,15 @@ pub(crate) struct Html<Msg> {
   #[cfg(all(target_arch = "wasm32", feature = "web"))]
   node: web_sys::Node,
   /// List of HTML attributes, such as `class` and `id`.
-  attributes: HashMap<String, String>,
+  attributes: Attributes,
   /// List of props, such as `value` and `checked`.
   #[cfg(all(target_arch = "wasm32", feature = "web"))]
   _props: HashMap<String, JsValue>,
   /// List of event listeners.
   #[cfg(all(target_arch = "wasm32", feature = "web"))]
   event_listeners: Vec<gloo::events::EventListener>,
+  /// Tasks updating dynamic attributes, cancelled with the element.
+  tasks: Vec<TaskGuard>,
   /// List of children to this [`View`].
   children: Vec<View<Msg>>,
 }
@@ -199,10 +308,8 @@ impl<Msg> fmt::Display for Html<Msg> {
 
     f.write_fmt(format_args!("<{name}"))?;
 
-    if !attributes.is_empty() {
-      for (key, value) in attributes {
-        f.write_fmt(format_args!(r#" {key}="{value}""#))?;
-      }
+    for (key, value) in attributes.borrow().iter() {
+      f.write_fmt(format_args!(r#" {key}="{value}""#))?;
     }
 
     if !children.is_empty() {
@@ -248,6 +355,7 @@ impl<Msg> Html<Msg> {
       _props: props,
       #[cfg(all(target_arch = "wasm32", feature = "web"))]
       event_listeners: Default::default(),
+      tasks: Default::default(),
       children: Default::default(),
     }
   }
@@ -262,13 +370,15 @@ pub(crate) struct VoidHtml {
   #[cfg(all(target_arch = "wasm32", feature = "web"))]
   node: web_sys::Node,
   /// List of HTML attributes, such as `class` and `id`.
-  attributes: HashMap<String, String>,
+  attributes: Attributes,
   /// List of props, such as `value` and `checked`.
   #[cfg(all(target_arch = "wasm32", feature = "web"))]
   _props: HashMap<String, JsValue>,
   /// List of event listeners.
   #[cfg(all(target_arch = "wasm32", feature = "web"))]
   event_listeners: Vec<gloo::events::EventListener>,
+  /// Tasks updating dynamic attributes, cancelled with the element.
+  tasks: Vec<TaskGuard>,
 }
 
 impl fmt::Display for VoidHtml {
@@ -280,10 +390,8 @@ impl fmt::Display for VoidHtml {
     {
       f.write_fmt(format_args!("<{name}"))?;
 
-      if !attributes.is_empty() {
-        for (key, value) in attributes {
-          f.write_fmt(format_args!(r#" {key}="{value}""#))?;
-        }
+      for (key, value) in attributes.borrow().iter() {
+        f.write_fmt(format_args!(r#" {key}="{value}""#))?;
       }
 
       f.write_str(">")
@@ -320,6 +428,7 @@ impl VoidHtml {
       _props: props,
       #[cfg(all(target_arch = "wasm32", feature = "web"))]
       event_listeners: Default::default(),
+      tasks: Default::default(),
     }
   }
 }
@@ -417,6 +526,9 @@ pub(crate) struct Component<Msg> {
   /// /* children */
   /// <!-- </ComponentName> --> <-- this is the closing comment
   closing: Comment,
+  /// Values which live for as long as the component, such as
+  /// [`TaskGuard`](crate::utils::TaskGuard)s.
+  owned: Vec<Box<dyn Any>>,
 }
 
 impl<Msg> fmt::Display for Component<Msg> {
@@ -459,8 +571,14 @@ impl<Msg> Component<Msg> {
       opening,
       children: Default::default(),
       closing,
+      owned: Default::default(),
     }
   }
+
+  /// Keeps `value` alive for as long as this component is.
+  pub fn own(&mut self, value: impl Any) {
+    self.owned.push(Box::new(value));
+  }
 }
 
 #[cfg(debug_assertions)]
@@ -489,3 +607,60 @@ fn assert_tag_name_is_valid(name: &str) {
     "all tag name ASCII characters must be `a-z` and `0-9`"
   );
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::{prelude::*, testing::TestApp};
+
+  #[test]
+  fn mapped_views_route_messages_to_parent() {
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum WidgetMsg {
+      Clicked,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+      Left(WidgetMsg),
+      Right(WidgetMsg),
+    }
+
+    let widget_cxs = Rc::new(RefCell::new(vec![]));
+
+    let widget = clone!([widget_cxs], move |cx: Ctx<WidgetMsg>| {
+      widget_cxs.borrow_mut().push(cx.clone());
+
+      button(cx).text("click")
+    });
+
+    let mut app = TestApp::new(App::new(
+      || (),
+      |_, _| (),
+      clone!([widget], move |_, cx: Ctx<Msg>| {
+        div(cx)
+          .map_child(Msg::Left, widget.clone())
+          // The view keeps the mapping it was built with
+          .child(move |cx| {
+            widget(cx.map(Msg::Left)).into_view().map_msg(Msg::Right)
+          })
+      }),
+    ));
+
+    for cx in widget_cxs.borrow().iter() {
+      cx.msg_dispatcher.dispatch(WidgetMsg::Clicked);
+    }
+
+    app.run_until_idle();
+
+    assert_eq!(
+      app.messages(),
+      [Msg::Left(WidgetMsg::Clicked), Msg::Left(WidgetMsg::Clicked)]
+    );
+    assert_eq!(
+      app.render_to_string(),
+      "<div><button>click</button><button>click</button></div>"
+    );
+  }
+}